@@ -1,13 +1,35 @@
-use arboard::Clipboard;
 use chrono::Local;
-use clap::{Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::*;
-use std::fs::OpenOptions;
-use std::io::Write;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
 use std::time::Duration;
 
-use crate::capture::CaptureSession;
+use crate::browse::{self, BrowseOutcome};
+use crate::capture::{self, CaptureSession};
+use crate::clipboard::Clipboard;
+use crate::compress::Compression;
 use crate::config::{Config, DEFAULT_SKIP_COMMANDS};
+use crate::diff::{self, DiffLine, DiffSummary, LineKind};
+use crate::error::{Result, TellmeError};
+use crate::export::{self, FormatArg, Log, LogFormat};
+use crate::follow;
+use crate::gc;
+use crate::hooks::{self, HookEvent};
+use crate::humanize;
+use crate::meta::CaptureMeta;
+use crate::notify::{self, Notification, Notifier};
+use crate::redact::Redactor;
+use crate::rerun;
+use crate::search::{self, PatternOptions, SearchLine};
+use crate::select::{LineRange, Selection};
+use crate::settings::Hook;
+use crate::share::{self, Paste};
+use crate::store::{CaptureQuery, CaptureRecord, CaptureStore};
+use crate::summary::{self, Summarizer};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Captures the output of the last command.")]
@@ -26,6 +48,58 @@ struct Cli {
     /// Keep ANSI colors in the file (default is to strip them)
     #[arg(short, long)]
     raw: bool,
+
+    /// Save a capture by id (or unique id prefix) instead of the last one
+    #[arg(long)]
+    id: Option<String>,
+
+    /// Save the most recent command of this shell that exited with a non-zero code
+    #[arg(long, conflicts_with = "id")]
+    last_failed: bool,
+
+    /// Act on the captures of another shell, by session id (or unique prefix) or PID
+    #[arg(long, global = true, value_name = "SESSION|PID")]
+    shell: Option<String>,
+
+    /// Compress the saved log (inferred from a .gz/.zst output name if omitted)
+    #[arg(long, value_enum)]
+    compress: Option<Compression>,
+
+    /// Add a summary of the errors and warnings found in the output
+    #[arg(long)]
+    summary: bool,
+
+    /// Format of the saved log; `auto` picks it from the output name (.json, .err)
+    #[arg(long, value_enum, default_value_t = FormatArg::default())]
+    format: FormatArg,
+
+    /// Keep only lines A to B of the output (e.g. 100:200, 100: or :200)
+    #[arg(long, value_name = "A:B", group = "select")]
+    lines: Option<LineRange>,
+
+    /// Keep only the first N lines of the output
+    #[arg(long, value_name = "N", group = "select")]
+    head: Option<usize>,
+
+    /// Keep only the last N lines of the output
+    #[arg(long, value_name = "N", group = "select")]
+    tail: Option<usize>,
+
+    /// Keep only the lines matching a regex
+    #[arg(long, value_name = "REGEX", group = "select")]
+    grep: Option<String>,
+
+    /// Lines of context around --grep matches
+    #[arg(short = 'C', long, value_name = "N", requires = "grep")]
+    context: Option<usize>,
+
+    /// Keep only the summary of errors and warnings, without the output
+    #[arg(long, group = "select")]
+    summary_only: bool,
+
+    /// Print JSON instead of coloured text (not for browse and follow)
+    #[arg(long, global = true)]
+    json: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -39,6 +113,125 @@ enum Commands {
     /// Show the current recording status.
     Status,
 
+    /// List recent captures.
+    List {
+        /// Only show commands that exited with a non-zero code.
+        #[arg(long)]
+        failed: bool,
+
+        /// Only show captures started within this time (e.g. 30m, 1h, 2d).
+        #[arg(long, value_parser = humanize::parse_duration)]
+        since: Option<Duration>,
+
+        /// Only show captures run in this directory.
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+
+        /// Only show captures whose command contains this text.
+        #[arg(long)]
+        grep: Option<String>,
+
+        /// Maximum number of captures to show (0 for all).
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Print a capture to the terminal, through $PAGER when interactive.
+    Show {
+        /// Capture id or unique id prefix (default: the last command).
+        id: Option<String>,
+
+        /// Keep ANSI colors (default is to strip them).
+        #[arg(short, long)]
+        raw: bool,
+
+        /// Never use a pager.
+        #[arg(long)]
+        no_pager: bool,
+    },
+
+    /// Search the output of all retained captures.
+    Grep {
+        /// Regular expression to search for.
+        pattern: String,
+
+        /// Match regardless of case.
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Treat the pattern as a literal string.
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Lines of context to show around each match.
+        #[arg(short = 'C', long, default_value_t = 2)]
+        context: usize,
+
+        /// Only search commands that exited with a non-zero code.
+        #[arg(long)]
+        failed_only: bool,
+    },
+
+    /// Run a captured command again and compare with the previous run.
+    Rerun {
+        /// Capture id or unique id prefix (default: the last command).
+        id: Option<String>,
+
+        /// Apply the environment variables recorded with the capture.
+        #[arg(long)]
+        env: bool,
+    },
+
+    /// Compare the output of two captures.
+    ///
+    /// With one id, compares that capture with the previous run of its command.
+    /// Without ids, compares the last command with its previous run.
+    Diff {
+        /// Capture id, or the older capture when two are given.
+        a: Option<String>,
+
+        /// The newer capture.
+        b: Option<String>,
+
+        /// Show the outputs side by side instead of as a unified diff.
+        #[arg(short = 'y', long)]
+        side_by_side: bool,
+
+        /// Ignore timestamps, durations, hex addresses and temp paths.
+        #[arg(short = 'N', long)]
+        normalize: bool,
+
+        /// Lines of context to show around each change.
+        #[arg(short = 'U', long, default_value_t = 3)]
+        context: usize,
+    },
+
+    /// Upload a capture to the paste service configured under [share].
+    ///
+    /// Tokens, passwords and private keys are redacted before uploading.
+    Share {
+        /// Capture id or unique id prefix (default: the last command).
+        id: Option<String>,
+
+        /// Also copy the URL of the paste to the clipboard.
+        #[arg(short, long)]
+        clipboard: bool,
+    },
+
+    /// Stream the output of a running command until it finishes.
+    ///
+    /// Follows the most recently started command, or the one of --shell.
+    Follow,
+
+    /// List shells with captures, and whether they are still running.
+    Shells,
+
+    /// Browse captured history in a full-screen terminal UI.
+    Browse,
+
+    /// Remove the files of shells that are no longer running.
+    Gc,
+
     /// Internal commands for shell integration.
     Internal {
         #[arg(long)]
@@ -47,8 +240,29 @@ enum Commands {
         #[arg(long)]
         prepare: Option<String>,
 
+        #[arg(long)]
+        finish: bool,
+
+        #[arg(long, requires = "finish")]
+        exit_code: Option<i32>,
+
+        #[arg(long)]
+        store: bool,
+
         #[arg(long)]
         cleanup: bool,
+
+        #[arg(long)]
+        gc: bool,
+
+        #[arg(long)]
+        new_session: bool,
+
+        #[arg(long)]
+        run_hook: bool,
+
+        #[arg(long)]
+        notify: bool,
     },
 
     /// Configure skip commands (commands that won't be captured).
@@ -70,13 +284,55 @@ enum Commands {
     },
 }
 
-fn handle_internal_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
+impl Commands {
+    /// Whether the command prints JSON with `--json`
+    ///
+    /// Only the full-screen browser and the live stream of `follow` cannot.
+    fn supports_json(&self) -> bool {
+        !matches!(self, Self::Browse | Self::Follow | Self::Internal { .. })
+    }
+}
+
+/// Parse the command line, rejecting `--json` where it would be ignored
+fn parse_cli() -> Cli {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if cli.json
+        && let Some(command) = &cli.command
+        && !command.supports_json()
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--json is not supported by 'tellme {}'",
+                    matches.subcommand_name().unwrap_or_default()
+                ),
+            )
+            .exit();
+    }
+    cli
+}
+
+fn handle_internal_command(cli: &Cli, config: &Config) -> Result<()> {
     if let Commands::Internal {
         should_prepare,
         prepare,
+        finish,
+        exit_code,
+        store,
         cleanup,
+        gc,
+        new_session,
+        run_hook,
+        notify,
     } = &cli.command.as_ref().unwrap()
     {
+        if *new_session {
+            println!("{}", Config::new_session());
+            return Ok(());
+        }
+
         let session = CaptureSession::new(config);
 
         if let Some(cmd) = should_prepare {
@@ -92,16 +348,187 @@ fn handle_internal_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
             return Ok(());
         }
 
+        if *finish {
+            if let Some(meta) = session.finish_command(*exit_code)? {
+                warn_background("archiving", capture::spawn_store());
+                let mut events = vec![HookEvent::new(Hook::OnCaptureComplete, &meta)];
+                if meta.failed() {
+                    events.push(HookEvent::new(Hook::OnFailure, &meta));
+                }
+                warn_background("hooks", hooks::spawn(config, &events));
+                warn_background("notifications", notify::spawn(config, &meta));
+            }
+            return Ok(());
+        }
+
+        if *store {
+            session.store_last()?;
+            return Ok(());
+        }
+
         if *cleanup {
             session.cleanup()?;
             return Ok(());
         }
+
+        if *gc {
+            gc::sweep(config)?;
+            return Ok(());
+        }
+
+        if *run_hook {
+            let event: HookEvent = serde_json::from_reader(std::io::stdin().lock())?;
+            let settings = &config.settings().hooks;
+            if let Some(command) = settings.command(event.hook) {
+                hooks::run(command, &event, Duration::from_secs(settings.timeout_secs))?;
+            }
+            return Ok(());
+        }
+
+        if *notify {
+            let meta: CaptureMeta = serde_json::from_reader(std::io::stdin().lock())?;
+            let settings = &config.settings().notify;
+            Notifier::new(settings).notify(&Notification::for_capture(&meta))?;
+            return Ok(());
+        }
     }
 
     Ok(())
 }
 
-fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
+/// Report background work that could not be started
+///
+/// Only a warning: hooks and notifications must never break the shell or a save.
+fn warn_background(what: &str, started: Result<()>) {
+    if let Err(e) = started {
+        eprintln!("{} Cannot run {}: {}.", "!".yellow(), what, e);
+    }
+}
+
+/// `--json` output of `on`, `off` and `status`
+#[derive(Serialize)]
+struct StatusReport<'a> {
+    recording: bool,
+    session: &'a str,
+    shell_pid: u32,
+}
+
+/// `--json` output of `config`
+#[derive(Serialize)]
+struct SkipListReport {
+    skip_commands: Vec<String>,
+    /// Whether the skip list was modified
+    changed: bool,
+}
+
+/// `--json` output of saving a capture
+#[derive(Serialize)]
+struct SaveReport<'a> {
+    command: &'a str,
+    /// The saved file, or `None` when copied to the clipboard
+    path: Option<&'a str>,
+    clipboard: bool,
+    /// Where the clipboard backend put the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    copied_to: Option<String>,
+    format: LogFormat,
+    compression: Compression,
+    /// Size of the captured output
+    output_bytes: usize,
+    /// Bytes written to the file or clipboard
+    saved_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<summary::Counts>,
+}
+
+/// `--json` output of `share`
+#[derive(Serialize)]
+struct ShareReport<'a> {
+    url: &'a str,
+    command: &'a str,
+    /// Size of the uploaded log
+    bytes: usize,
+    /// Number of secrets replaced before uploading
+    redacted: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    copied_to: Option<String>,
+}
+
+/// `--json` output of `show`
+#[derive(Serialize)]
+struct ShowReport<'a> {
+    #[serde(flatten)]
+    capture: &'a CaptureMeta,
+    output: &'a str,
+}
+
+/// A capture with matches, in the `--json` output of `grep`
+#[derive(Serialize)]
+struct GrepReport<'a> {
+    capture: &'a CaptureMeta,
+    /// Runs of matching lines with their context
+    groups: Vec<Vec<SearchLine<'a>>>,
+}
+
+/// `--json` output of `rerun`
+#[derive(Serialize)]
+struct RerunReport<'a> {
+    previous: &'a CaptureMeta,
+    capture: &'a CaptureMeta,
+    output: &'a str,
+    /// Lines changed compared with the previous run
+    diff: DiffSummary,
+}
+
+/// `--json` output of `diff`
+#[derive(Serialize)]
+struct DiffReport<'a> {
+    old: &'a CaptureMeta,
+    new: &'a CaptureMeta,
+    summary: DiffSummary,
+    /// The rendered diff, empty when the outputs are identical
+    lines: Vec<DiffLine>,
+}
+
+/// Print a value as the JSON output of a command
+fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_status(config: &Config, json: bool) -> Result<()> {
+    if json {
+        return print_json(&StatusReport {
+            recording: config.is_recording_enabled(),
+            session: config.session(),
+            shell_pid: config.pid(),
+        });
+    }
+
+    if config.is_recording_enabled() {
+        println!("tellme recording is {}", "ENABLED".bold().green());
+    } else {
+        println!("tellme recording is {}", "DISABLED".bold().yellow());
+    }
+    Ok(())
+}
+
+fn set_recording(config: &mut Config, enabled: bool, json: bool) -> Result<()> {
+    config.set_recording_enabled(enabled)?;
+    if json {
+        return print_status(config, true);
+    }
+
+    let state = if enabled {
+        "ENABLED".bold().green()
+    } else {
+        "DISABLED".bold().yellow()
+    };
+    println!("{} tellme recording is now {}", "✔".green(), state);
+    Ok(())
+}
+
+fn handle_config_command(cli: &Cli, config: &Config) -> Result<()> {
     if let Commands::Config {
         list,
         add,
@@ -110,10 +537,18 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
         reset,
     } = &cli.command.as_ref().unwrap()
     {
-        let config = config.clone();
+        let mut skip_commands = config.skip_commands();
+        let report = |skip_commands: Vec<String>, changed: bool| {
+            print_json(&SkipListReport {
+                skip_commands,
+                changed,
+            })
+        };
 
         if *list {
-            let skip_commands = config.skip_commands();
+            if cli.json {
+                return report(skip_commands, false);
+            }
             if skip_commands.is_empty() {
                 println!("{}", "No commands in skip list.".dimmed());
             } else {
@@ -126,10 +561,15 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
         }
 
         if let Some(cmd) = add {
-            let mut skip_commands = config.skip_commands();
-            if !skip_commands.contains(cmd) {
+            let changed = !skip_commands.contains(cmd);
+            if changed {
                 skip_commands.push(cmd.clone());
                 config.save_skip_commands(&skip_commands)?;
+            }
+            if cli.json {
+                return report(skip_commands, changed);
+            }
+            if changed {
                 println!("{} Added '{}' to skip list", "✔".green(), cmd.bold());
             } else {
                 println!("{} '{}' is already in skip list", "!".yellow(), cmd.bold());
@@ -138,10 +578,15 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
         }
 
         if let Some(cmd) = remove {
-            let mut skip_commands = config.skip_commands();
-            if skip_commands.contains(cmd) {
+            let changed = skip_commands.contains(cmd);
+            if changed {
                 skip_commands.retain(|c| c != cmd);
                 config.save_skip_commands(&skip_commands)?;
+            }
+            if cli.json {
+                return report(skip_commands, changed);
+            }
+            if changed {
                 println!("{} Removed '{}' from skip list", "✔".green(), cmd.bold());
             } else {
                 println!("{} '{}' is not in skip list", "✘".red(), cmd.bold());
@@ -151,6 +596,9 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
 
         if *clear {
             config.save_skip_commands(&Vec::new())?;
+            if cli.json {
+                return report(Vec::new(), !skip_commands.is_empty());
+            }
             println!("{} Cleared all skip commands", "✔".green());
             return Ok(());
         }
@@ -161,6 +609,10 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
                 .map(|s| s.to_string())
                 .collect();
             config.save_skip_commands(&defaults)?;
+            if cli.json {
+                let changed = skip_commands != defaults;
+                return report(defaults, changed);
+            }
             println!("{} Reset skip commands to defaults", "✔".green());
             return Ok(());
         }
@@ -169,87 +621,879 @@ fn handle_config_command(cli: &Cli, config: &Config) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn handle_get_last_output(cli: &Cli, config: &Config) -> anyhow::Result<()> {
-    let session = CaptureSession::new(config);
+fn handle_list_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::List {
+        failed,
+        since,
+        cwd,
+        grep,
+        limit,
+    } = &cli.command.as_ref().unwrap()
+    {
+        let query = CaptureQuery {
+            failed: *failed,
+            since: since.map(|d| Local::now() - d),
+            cwd: cwd.clone(),
+            grep: grep.clone(),
+            shell: cli.shell.as_ref().map(|_| config.session().to_string()),
+        };
+        let mut records = CaptureStore::new(config).query(&query)?;
+        if *limit > 0 {
+            records.truncate(*limit);
+        }
+
+        if cli.json {
+            return print_json(&records);
+        }
+
+        if records.is_empty() {
+            println!("{}", "No captures found.".dimmed());
+            return Ok(());
+        }
+
+        println!(
+            "{}",
+            format!(
+                "{:<8}  {:<14}  {:>7}  {:>4}  {:>8}  {:>7}  {}",
+                "ID", "TIME", "PID", "EXIT", "DURATION", "SIZE", "COMMAND"
+            )
+            .bold()
+        );
+        for record in &records {
+            print_record_row(record);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_record_row(record: &CaptureRecord) {
+    let meta = &record.meta;
+
+    let exit = match meta.exit_code {
+        Some(0) => format!("{:>4}", 0).green(),
+        Some(code) => format!("{:>4}", code).red(),
+        None if meta.is_complete() => format!("{:>4}", "?").dimmed(),
+        None => format!("{:>4}", "…").yellow(),
+    };
+    let duration = meta
+        .duration()
+        .map(humanize::format_duration)
+        .unwrap_or_else(|| "-".to_string());
+
+    println!(
+        "{}  {:<14}  {:>7}  {}  {:>8}  {:>7}  {}",
+        meta.short_id().cyan(),
+        meta.started_at.format("%m-%d %H:%M:%S"),
+        meta.shell_pid,
+        exit,
+        duration,
+        humanize::format_size(meta.size),
+        humanize::truncate(&meta.command, 60)
+    );
+}
+
+/// The error for a shell without a previous command
+fn no_previous(config: &Config) -> TellmeError {
+    if config.is_recording_enabled() {
+        TellmeError::NoPreviousCapture
+    } else {
+        TellmeError::RecordingDisabled
+    }
+}
+
+/// Find a capture record: the given id, or this shell's last capture
+fn select_record(config: &Config, id: Option<&str>) -> Result<CaptureRecord> {
+    let store = CaptureStore::new(config);
+    match id {
+        Some(id) => store
+            .find(id)?
+            .ok_or_else(|| TellmeError::NotFound(format!("No capture with id '{}'", id))),
+        None => store.current().ok_or_else(|| no_previous(config)),
+    }
+}
+
+/// Load the metadata and output of a capture: the given id, or the last one
+fn select_capture(config: &Config, id: Option<&str>) -> Result<(CaptureMeta, Vec<u8>)> {
+    if id.is_some() {
+        let record = select_record(config, id)?;
+        let content = record.read_output()?;
+        return Ok((record.meta, content));
+    }
 
+    let session = CaptureSession::new(config);
     if !session.has_previous() {
-        eprintln!("{}", "Error: No previous command record found.".red());
-        eprint!("Maybe recording was disabled? Run 'tellme status' to check, ");
-        eprintln!("or last command was skipped.");
-        return Ok(());
+        return Err(no_previous(config));
     }
+    session.read_last_capture()
+}
+
+fn handle_show_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Show { id, raw, no_pager } = &cli.command.as_ref().unwrap() {
+        let (meta, content) = select_capture(config, id.as_deref())?;
+        let command = &meta.command;
 
-    let last_cmd = session.read_cmd_file()?;
-    let last_content = session.read_output()?;
+        let content = if *raw {
+            content
+        } else {
+            strip_ansi_escapes::strip(&content)
+        };
+        let content_str = String::from_utf8_lossy(&content);
+
+        if cli.json {
+            return print_json(&ShowReport {
+                capture: &meta,
+                output: &content_str,
+            });
+        }
 
+        if !*no_pager && std::io::stdout().is_terminal() {
+            let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+            if let Ok(mut child) = ProcessCommand::new("sh")
+                .arg("-c")
+                .arg(&pager)
+                .stdin(Stdio::piped())
+                .spawn()
+            {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // The pager may quit before reading everything
+                    export::write_log(&mut stdin, &Log::new(command, &content_str)).ok();
+                }
+                child.wait()?;
+                return Ok(());
+            }
+        }
+
+        export::write_log(
+            &mut std::io::stdout().lock(),
+            &Log::new(command, &content_str),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn handle_grep_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Grep {
+        pattern,
+        ignore_case,
+        fixed_strings,
+        context,
+        failed_only,
+    } = &cli.command.as_ref().unwrap()
+    {
+        let regex = search::build_pattern(
+            pattern,
+            PatternOptions {
+                ignore_case: *ignore_case,
+                fixed_strings: *fixed_strings,
+            },
+        )?;
+        let query = CaptureQuery {
+            failed: *failed_only,
+            ..CaptureQuery::default()
+        };
+
+        let records = CaptureStore::new(config).query(&query)?;
+        let mut texts = Vec::new();
+        for record in &records {
+            // Captures can disappear while we search (e.g. a shell exits)
+            let Ok(content) = record.read_output() else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&strip_ansi_escapes::strip(&content)).to_string();
+            texts.push((record, text));
+        }
+
+        let mut reports = Vec::new();
+        for (record, text) in &texts {
+            let groups = search::search(text, &regex, *context);
+            if !groups.is_empty() {
+                reports.push(GrepReport {
+                    capture: &record.meta,
+                    groups,
+                });
+            }
+        }
+        if cli.json {
+            return print_json(&reports);
+        }
+
+        let mut matched_captures = 0;
+        let mut matched_lines = 0;
+        for GrepReport { capture, groups } in &reports {
+            if matched_captures > 0 {
+                println!();
+            }
+            matched_captures += 1;
+
+            let meta = capture;
+            let status = match meta.exit_code {
+                Some(code) => format!("exit {}", code),
+                None => "running".to_string(),
+            };
+            println!(
+                "{}  {}  {}",
+                meta.short_id().cyan(),
+                meta.command.bold(),
+                format!("({}, {})", status, meta.started_at.format("%m-%d %H:%M")).dimmed()
+            );
+
+            for (i, group) in groups.iter().enumerate() {
+                if i > 0 {
+                    println!("{}", "--".dimmed());
+                }
+                for line in group {
+                    if line.is_match {
+                        matched_lines += 1;
+                        let highlighted = regex.replace_all(line.text, |caps: &regex::Captures| {
+                            caps[0].red().bold().to_string()
+                        });
+                        println!("{}: {}", format!("{:>5}", line.number).green(), highlighted);
+                    } else {
+                        println!("{}- {}", format!("{:>5}", line.number).dimmed(), line.text);
+                    }
+                }
+            }
+        }
+
+        if matched_captures == 0 {
+            println!("{}", "No matches.".dimmed());
+        } else {
+            println!(
+                "\n{}",
+                format!(
+                    "{} matching lines in {} captures",
+                    matched_lines, matched_captures
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_rerun_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Rerun { id, env } = &cli.command.as_ref().unwrap() {
+        let previous = select_record(config, id.as_deref())?;
+        rerun_and_compare(config, &previous, *env, cli.json)?;
+    }
+
+    Ok(())
+}
+
+/// Run a capture again and summarize how the new run differs
+///
+/// With `json`, the output is not streamed but reported with the summary.
+fn rerun_and_compare(
+    config: &Config,
+    previous: &CaptureRecord,
+    with_env: bool,
+    json: bool,
+) -> Result<()> {
+    let clean =
+        |content: &[u8]| String::from_utf8_lossy(&strip_ansi_escapes::strip(content)).to_string();
+    let previous_output = clean(&previous.read_output()?);
+
+    if json {
+        let (meta, output) = rerun::rerun(config, &previous.meta, with_env, &mut std::io::sink())?;
+        let output = clean(&output);
+        return print_json(&RerunReport {
+            previous: &previous.meta,
+            capture: &meta,
+            diff: diff::summarize(&previous_output, &output),
+            output: &output,
+        });
+    }
+
+    println!(
+        "{} {}  {}",
+        "$".dimmed(),
+        previous.meta.command.bold(),
+        format!("(in {})", previous.meta.cwd.display()).dimmed()
+    );
+    let (meta, output) = rerun::rerun(config, &previous.meta, with_env, &mut std::io::stdout())?;
+    let summary = diff::summarize(&previous_output, &clean(&output));
+
+    let exit = |code: Option<i32>| code.map_or_else(|| "?".to_string(), |c| c.to_string());
+    let colored_exit = |code: Option<i32>| match code {
+        Some(0) => exit(code).green(),
+        _ => exit(code).red(),
+    };
+
+    println!();
+    if meta.exit_code == previous.meta.exit_code {
+        println!("Exit code: {} (unchanged)", colored_exit(meta.exit_code));
+    } else {
+        println!(
+            "Exit code: {} → {}",
+            colored_exit(previous.meta.exit_code),
+            colored_exit(meta.exit_code)
+        );
+    }
+    if summary.is_unchanged() {
+        println!(
+            "Output unchanged compared with {}",
+            previous.meta.short_id().cyan()
+        );
+    } else {
+        println!(
+            "Output: {} lines added, {} removed compared with {}",
+            format!("+{}", summary.added).green(),
+            format!("-{}", summary.removed).red(),
+            previous.meta.short_id().cyan()
+        );
+    }
+    println!("{}", format!("New capture: {}", meta.short_id()).dimmed());
+
+    Ok(())
+}
+
+fn handle_diff_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Diff {
+        a,
+        b,
+        side_by_side,
+        normalize,
+        context,
+    } = &cli.command.as_ref().unwrap()
+    {
+        let store = CaptureStore::new(config);
+        let (old, new) = match (a, b) {
+            (Some(a), Some(b)) => (
+                select_record(config, Some(a))?,
+                select_record(config, Some(b))?,
+            ),
+            _ => {
+                let new = select_record(config, a.as_deref())?;
+                let old = store.previous_run(&new)?.ok_or_else(|| {
+                    TellmeError::NotFound(format!(
+                        "No earlier run of '{}' to compare with",
+                        new.meta.command
+                    ))
+                })?;
+                (old, new)
+            }
+        };
+
+        let read = |record: &CaptureRecord| -> Result<String> {
+            let content = strip_ansi_escapes::strip(record.read_output()?);
+            let text = String::from_utf8_lossy(&content).to_string();
+            Ok(if *normalize {
+                diff::normalize(&text)
+            } else {
+                text
+            })
+        };
+        let (old_text, new_text) = (read(&old)?, read(&new)?);
+
+        let name = |record: &CaptureRecord| {
+            format!(
+                "{} {} ({})",
+                record.meta.short_id(),
+                record.meta.command,
+                record.meta.started_at.format("%m-%d %H:%M:%S")
+            )
+        };
+        if cli.json {
+            let lines = if old_text == new_text {
+                Vec::new()
+            } else {
+                diff::unified(&old_text, &new_text, (&name(&old), &name(&new)), *context)
+            };
+            return print_json(&DiffReport {
+                old: &old.meta,
+                new: &new.meta,
+                summary: diff::summarize(&old_text, &new_text),
+                lines,
+            });
+        }
+
+        if old_text == new_text {
+            println!(
+                "{} Outputs of {} and {} are identical.",
+                "✔".green(),
+                old.meta.short_id().cyan(),
+                new.meta.short_id().cyan()
+            );
+            return Ok(());
+        }
+
+        let lines = if *side_by_side {
+            let width = ratatui::crossterm::terminal::size()
+                .map(|(columns, _)| columns as usize)
+                .unwrap_or(160);
+            println!("{}", format!("<<< {}", name(&old)).bold());
+            println!("{}", format!(">>> {}", name(&new)).bold());
+            diff::side_by_side(&old_text, &new_text, *context, width)
+        } else {
+            diff::unified(&old_text, &new_text, (&name(&old), &name(&new)), *context)
+        };
+
+        for line in lines {
+            let text = match line.kind {
+                LineKind::Header => line.text.bold(),
+                LineKind::Hunk => line.text.cyan(),
+                LineKind::Equal => line.text.normal(),
+                LineKind::Delete => line.text.red(),
+                LineKind::Insert => line.text.green(),
+                LineKind::Change => line.text.yellow(),
+            };
+            println!("{}", text);
+        }
+    }
+
+    Ok(())
+}
+
+/// Load the command and output of this shell's most recent failed capture
+///
+/// Prints which command was picked, unless `quiet`.
+fn select_last_failed(config: &Config, quiet: bool) -> Result<(CaptureMeta, Vec<u8>)> {
+    let query = CaptureQuery {
+        failed: true,
+        shell: Some(config.session().to_string()),
+        ..CaptureQuery::default()
+    };
+    let record = CaptureStore::new(config)
+        .query(&query)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| TellmeError::NotFound("No failed command found in this shell".into()))?;
+
+    if !quiet {
+        println!(
+            "{}",
+            format!(
+                "Last failed: {} (exit {}, {})",
+                record.meta.command,
+                record.meta.exit_code.unwrap_or_default(),
+                record.meta.started_at.format("%m-%d %H:%M")
+            )
+            .dimmed()
+        );
+    }
+    let content = record.read_output()?;
+    Ok((record.meta, content))
+}
+
+fn handle_share_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Share { id, clipboard } = &cli.command.as_ref().unwrap() {
+        let (meta, content) = select_capture(config, id.as_deref())?;
+        let command = meta.command;
+        let output = String::from_utf8_lossy(&strip_ansi_escapes::strip(&content)).to_string();
+
+        let settings = &config.settings().share;
+        let redactor = Redactor::new(&settings.redact)?;
+        let (command, command_secrets) = redactor.redact(&command);
+        let (output, output_secrets) = redactor.redact(&output);
+        let paste = Paste {
+            command: &command,
+            output: &output,
+        };
+        let url = share::upload(settings, &paste, &config.share_file())?;
+
+        let copied_to = if *clipboard {
+            Some(Clipboard::new(config).copy(&url)?)
+        } else {
+            None
+        };
+        let report = ShareReport {
+            url: &url,
+            command: &command,
+            bytes: paste.content().len(),
+            redacted: command_secrets + output_secrets,
+            copied_to,
+        };
+        if cli.json {
+            return print_json(&report);
+        }
+
+        println!(
+            "{} Shared '{}': {}",
+            "✔".green(),
+            command.bold(),
+            url.cyan()
+        );
+        if let Some(target) = &report.copied_to {
+            println!("{}", format!("URL copied to {}.", target).dimmed());
+        }
+        if report.redacted > 0 {
+            println!(
+                "{}",
+                format!("{} secrets redacted before uploading", report.redacted).dimmed()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_follow_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Follow = &cli.command.as_ref().unwrap() {
+        let store = CaptureStore::new(config);
+        let record = match cli.shell {
+            Some(_) => store.current(),
+            None => store.active().into_iter().next(),
+        };
+        let record = record.ok_or_else(|| {
+            TellmeError::NotFound(match &cli.shell {
+                Some(shell) => format!("No capture found for shell {}", shell),
+                None => "No running command found".to_string(),
+            })
+        })?;
+
+        // Status lines go to stderr so the output can be redirected cleanly
+        eprintln!(
+            "{}",
+            format!(
+                "Following '{}' (shell {}, started {})",
+                record.meta.command,
+                record.meta.shell_pid,
+                record.meta.started_at.format("%H:%M:%S")
+            )
+            .dimmed()
+        );
+
+        let finished = follow::follow(
+            &store,
+            &record,
+            &mut std::io::stdout().lock(),
+            follow::POLL_INTERVAL,
+        )?;
+
+        eprintln!();
+        match finished {
+            Some(meta) => {
+                let duration = meta.duration().map(humanize::format_duration);
+                let summary = format!(
+                    "Finished with exit {} after {}",
+                    meta.exit_code
+                        .map_or_else(|| "?".to_string(), |c| c.to_string()),
+                    duration.as_deref().unwrap_or("?")
+                );
+                if meta.failed() {
+                    eprintln!("{} {}", "✘".red(), summary);
+                } else {
+                    eprintln!("{} {}", "✔".green(), summary);
+                }
+            }
+            None => eprintln!(
+                "{} The capture was removed before it finished.",
+                "!".yellow()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_shells_command(cli: &Cli, config: &Config) -> Result<()> {
+    if let Commands::Shells = &cli.command.as_ref().unwrap() {
+        let shells = CaptureStore::new(config).shells()?;
+
+        if cli.json {
+            return print_json(&shells);
+        }
+
+        if shells.is_empty() {
+            println!("{}", "No shells with captures found.".dimmed());
+            return Ok(());
+        }
+
+        println!(
+            "{}",
+            format!(
+                "{:<8}  {:>7}    {:<5}  {:>8}  {:<14}  {}",
+                "SESSION", "PID", "ALIVE", "CAPTURES", "LAST RUN", "LAST COMMAND"
+            )
+            .bold()
+        );
+        for shell in &shells {
+            // Mark the shell tellme was started from
+            let marker = if shell.session == config.session() {
+                "*"
+            } else {
+                " "
+            };
+            let pid = shell
+                .pid
+                .map_or_else(|| "-".to_string(), |pid| pid.to_string());
+            let alive = if shell.alive {
+                format!("{:<5}", "yes").green()
+            } else {
+                format!("{:<5}", "no").red()
+            };
+            let (time, command) = match &shell.last {
+                Some(meta) => (
+                    meta.started_at.format("%m-%d %H:%M:%S").to_string(),
+                    humanize::truncate(&meta.command, 60),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            println!(
+                "{:<8}  {:>7} {}  {}  {:>8}  {:<14}  {}",
+                short_session(&shell.session),
+                pid,
+                marker,
+                alive,
+                shell.captures,
+                time,
+                command
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The first characters of a session id, enough to tell shells apart
+fn short_session(session: &str) -> &str {
+    session.get(..8).unwrap_or(session)
+}
+
+fn handle_gc_command(cli: &Cli, config: &Config) -> Result<()> {
+    let swept = gc::sweep(config)?;
+    if cli.json {
+        return print_json(&swept);
+    }
+
+    if swept.is_empty() {
+        println!("{}", "Nothing to clean up.".dimmed());
+    } else {
+        let pids: Vec<String> = swept
+            .iter()
+            .map(|shell| match shell.pid {
+                Some(pid) => pid.to_string(),
+                None => short_session(&shell.session).to_string(),
+            })
+            .collect();
+        println!(
+            "{} Removed the files of {} dead shells ({})",
+            "✔".green(),
+            swept.len(),
+            pids.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The lines picked by --lines, --head, --tail or --grep
+fn selection(cli: &Cli) -> Result<Selection> {
+    Ok(if let Some(range) = cli.lines {
+        Selection::Lines(range)
+    } else if let Some(count) = cli.head {
+        Selection::Head(count)
+    } else if let Some(count) = cli.tail {
+        Selection::Tail(count)
+    } else if let Some(pattern) = &cli.grep {
+        Selection::Grep {
+            regex: search::build_pattern(pattern, PatternOptions::default())?,
+            context: cli.context.unwrap_or(0),
+        }
+    } else {
+        Selection::All
+    })
+}
+
+fn handle_get_last_output(cli: &Cli, config: &Config) -> Result<()> {
+    let selection = selection(cli)?;
+    let selected = if cli.last_failed {
+        select_last_failed(config, cli.json)?
+    } else {
+        select_capture(config, cli.id.as_deref())?
+    };
+    let (last_meta, last_content) = selected;
+    let last_cmd = &last_meta.command;
+
+    let format = cli.format.resolve(cli.output.as_deref().map(Path::new));
+
+    let diagnostics = if cli.summary || cli.summary_only || format.lists_diagnostics() {
+        let summarizer = Summarizer::from_settings(&config.settings().summary)?;
+        let clean = strip_ansi_escapes::strip(&last_content);
+        Some(summarizer.summarize(&String::from_utf8_lossy(&clean)))
+    } else {
+        None
+    };
+
+    let output_bytes = last_content.len();
     let final_content = if cli.raw {
         last_content
     } else {
         strip_ansi_escapes::strip(&last_content)
     };
 
-    let content_str = String::from_utf8_lossy(&final_content).to_string();
+    let mut content_str = selection.apply(&String::from_utf8_lossy(&final_content));
+    if content_str.is_empty() && !matches!(selection, Selection::All) {
+        return Err(TellmeError::NotFound(
+            "No lines of the output were selected".into(),
+        ));
+    }
+
+    let settings = &config.settings().clipboard;
+    let size = content_str.len() as u64;
+    // A limit of 0 turns the warning off
+    let limit = settings.max_size_kb * 1024;
+    if cli.clipboard && !cli.summary_only && limit > 0 && size > limit {
+        if matches!(selection, Selection::All) && settings.auto_tail > 0 {
+            content_str = Selection::Tail(settings.auto_tail).apply(&content_str);
+            eprintln!(
+                "{} Output is {}, copying only the last {} lines",
+                "!".yellow(),
+                humanize::format_size(size),
+                settings.auto_tail
+            );
+        } else {
+            eprintln!(
+                "{} Copying {} of output; use --tail, --grep or --summary-only to copy less",
+                "!".yellow(),
+                humanize::format_size(size)
+            );
+        }
+    }
+
+    let mut log = Log::new(last_cmd, &content_str).with_cwd(&last_meta.cwd);
+    if let Some(diagnostics) = &diagnostics {
+        log = log.with_summary(diagnostics);
+    }
+    if cli.summary_only {
+        log = log.without_output();
+    }
+    let found = diagnostics
+        .as_deref()
+        .map(|d| format!(" ({})", summary::describe(d)))
+        .unwrap_or_default();
 
+    let mut report = SaveReport {
+        command: last_cmd,
+        path: None,
+        clipboard: cli.clipboard,
+        copied_to: None,
+        format,
+        compression: Compression::None,
+        output_bytes,
+        saved_bytes: 0,
+        summary: diagnostics.as_deref().map(summary::count),
+    };
+
+    let target_file;
     if cli.clipboard {
-        let mut clipboard = Clipboard::new().unwrap();
-        clipboard.set_text(content_str).unwrap();
-        std::thread::sleep(Duration::from_millis(100));
+        let text = if diagnostics.is_some() || format != LogFormat::Text {
+            String::from_utf8_lossy(&export::render(&log, format)?).to_string()
+        } else {
+            content_str.clone()
+        };
+        let target = Clipboard::new(config).copy(&text)?;
+        report.saved_bytes = text.len() as u64;
 
-        println!("{} Output copied to clipboard.", "✔".green());
+        if !cli.json {
+            println!("{} Output copied to {}.{}", "✔".green(), target, found);
+        }
+        report.copied_to = Some(target);
     } else {
-        let target_file = match &cli.output {
+        let compression = cli.compress.unwrap_or_else(|| match &cli.output {
+            Some(name) => Compression::from_path(Path::new(name)),
+            None => Compression::None,
+        });
+        target_file = match &cli.output {
             Some(name) => name.clone(),
-            None => {
-                let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-                format!("tellme_{}.log", timestamp)
-            }
+            None => export::default_log_name(format, compression),
         };
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&target_file)?;
-        writeln!(file, "Command:\n{}", last_cmd)?;
-        writeln!(file, "=============================\n")?;
-        writeln!(file, "{}", content_str)?;
+        report.saved_bytes = export::save_log(&target_file, &log, format, compression)?;
+        report.path = Some(&target_file);
+        report.compression = compression;
 
-        println!("{} Output saved to {}", "✔".green(), target_file.bold());
+        if !cli.json {
+            println!(
+                "{} Output saved to {}{}",
+                "✔".green(),
+                target_file.bold(),
+                found
+            );
+        }
+
+        let mut event = HookEvent::new(Hook::OnSave, &last_meta);
+        event.saved_path = Some(std::path::absolute(&target_file)?);
+        warn_background("hooks", hooks::spawn(config, &[event]));
     }
 
+    if cli.json {
+        print_json(&report)?;
+    }
     Ok(())
 }
 
 pub fn app() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let config = Config::new()?;
+    let cli = parse_cli();
+    // Otherwise `colored` drops colours itself when stdout is not a terminal
+    if cli.json {
+        colored::control::set_override(false);
+    }
+    let mut config = if let Some(Commands::Internal { should_prepare, .. }) = &cli.command {
+        // A broken config.toml must not break the prompt: report it once per
+        // command line, from the first hook, and carry on with the defaults
+        let (config, invalid) = Config::new_lenient()?;
+        if let Some(e) = invalid
+            && should_prepare.is_some()
+        {
+            eprintln!("{} {}; using the default settings.", "!".yellow(), e);
+        }
+        config
+    } else {
+        Config::new()?
+    };
+    if let Some(key) = &cli.shell {
+        let shell = CaptureStore::new(&config)
+            .find_shell(key)?
+            .ok_or_else(|| TellmeError::NotFound(format!("No shell '{}' found", key)))?;
+        config.attach(&shell.session, shell.pid.unwrap_or_default());
+    }
 
     match cli.command {
         Some(Commands::On) => {
-            let mut config_mut = config;
-            config_mut.set_recording_enabled(true)?;
-            println!(
-                "{} tellme recording is now {}",
-                "✔".green(),
-                "ENABLED".bold().green()
-            );
+            set_recording(&mut config, true, cli.json)?;
         }
         Some(Commands::Off) => {
-            let mut config_mut = config;
-            config_mut.set_recording_enabled(false)?;
-            println!(
-                "{} tellme recording is now {}",
-                "✔".green(),
-                "DISABLED".bold().yellow()
-            );
+            set_recording(&mut config, false, cli.json)?;
         }
         Some(Commands::Status) => {
-            if config.is_recording_enabled() {
-                println!("tellme recording is {}", "ENABLED".bold().green());
-            } else {
-                println!("tellme recording is {}", "DISABLED".bold().yellow());
+            print_status(&config, cli.json)?;
+        }
+        Some(Commands::List { .. }) => {
+            handle_list_command(&cli, &config)?;
+        }
+        Some(Commands::Show { .. }) => {
+            handle_show_command(&cli, &config)?;
+        }
+        Some(Commands::Grep { .. }) => {
+            handle_grep_command(&cli, &config)?;
+        }
+        Some(Commands::Rerun { .. }) => {
+            handle_rerun_command(&cli, &config)?;
+        }
+        Some(Commands::Diff { .. }) => {
+            handle_diff_command(&cli, &config)?;
+        }
+        Some(Commands::Share { .. }) => {
+            handle_share_command(&cli, &config)?;
+        }
+        Some(Commands::Follow) => {
+            handle_follow_command(&cli, &config)?;
+        }
+        Some(Commands::Shells) => {
+            handle_shells_command(&cli, &config)?;
+        }
+        Some(Commands::Browse) => {
+            if let BrowseOutcome::Rerun(record) = browse::browse(&config)? {
+                rerun_and_compare(&config, &record, false, false)?;
             }
         }
+        Some(Commands::Gc) => {
+            handle_gc_command(&cli, &config)?;
+        }
         Some(Commands::Internal { .. }) => {
             handle_internal_command(&cli, &config)?;
         }
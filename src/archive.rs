@@ -0,0 +1,381 @@
+use chrono::Local;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, TellmeError};
+use crate::lock::FileLock;
+use crate::meta::CaptureMeta;
+use crate::secure;
+use crate::settings::ArchiveSettings;
+
+/// Persistent store of completed captures
+///
+/// Layout of the archive directory:
+/// - `index.json`: metadata of every archived capture, oldest first
+/// - `index.lock`: held while the index is read and rewritten, as every
+///   shell writes to the same archive
+/// - `<id>.out`: the captured output, stored as it was in the temp dir
+#[derive(Debug, Clone)]
+pub struct Archive {
+    /// Root directory of the archive
+    root: PathBuf,
+}
+
+impl Archive {
+    /// Create an Archive rooted at the given directory
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Get the root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the index file path
+    fn index_file(&self) -> PathBuf {
+        self.root.join("index.json")
+    }
+
+    /// Get the output file path for a capture
+    pub fn output_file(&self, id: &str) -> PathBuf {
+        self.root.join(format!("{}.out", id))
+    }
+
+    /// Load all archived capture metadata, oldest first
+    pub fn entries(&self) -> Result<Vec<CaptureMeta>> {
+        let path = self.index_file();
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| TellmeError::Corrupt {
+                path,
+                message: e.to_string(),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(TellmeError::io(format!("Cannot read {}", path.display()))(
+                e,
+            )),
+        }
+    }
+
+    /// Write the index atomically
+    ///
+    /// Callers must hold the index lock from reading the entries on.
+    fn save_entries(&self, entries: &[CaptureMeta]) -> Result<()> {
+        secure::write_atomic(&self.index_file(), serde_json::to_vec_pretty(entries)?)
+    }
+
+    /// Take the index lock, creating the archive if needed
+    fn lock(&self) -> Result<FileLock> {
+        secure::private_dir(&self.root)?;
+        FileLock::exclusive(&self.root.join("index.lock"))
+    }
+
+    /// Add a completed capture to the archive
+    ///
+    /// `output` is stored as-is, so compressed captures stay compressed.
+    /// Fails if a capture with the same id is already archived.
+    pub fn add(&self, meta: &CaptureMeta, output: &[u8]) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+        if entries.iter().any(|e| e.id == meta.id) {
+            return Err(TellmeError::InvalidInput(format!(
+                "Capture {} is already archived in {}",
+                meta.id,
+                self.root.display()
+            )));
+        }
+
+        secure::write_private(&self.output_file(&meta.id), output)?;
+        entries.push(meta.clone());
+        entries.sort_by_key(|e| e.started_at);
+        self.save_entries(&entries)
+    }
+
+    /// Replace the metadata of an archived capture
+    ///
+    /// Returns `true` if the capture existed.
+    pub fn update(&self, meta: &CaptureMeta) -> Result<bool> {
+        if !self.root.exists() {
+            return Ok(false);
+        }
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+        let Some(entry) = entries.iter_mut().find(|e| e.id == meta.id) else {
+            return Ok(false);
+        };
+        *entry = meta.clone();
+        self.save_entries(&entries)?;
+        Ok(true)
+    }
+
+    /// Remove a capture from the archive
+    ///
+    /// Returns `true` if the capture existed.
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        if !self.root.exists() {
+            return Ok(false);
+        }
+        let _lock = self.lock()?;
+        let mut entries = self.entries()?;
+        let before = entries.len();
+        entries.retain(|e| e.id != id);
+        if entries.len() == before {
+            return Ok(false);
+        }
+
+        self.save_entries(&entries)?;
+        let output = self.output_file(id);
+        if output.exists() {
+            fs::remove_file(output)?;
+        }
+        Ok(true)
+    }
+
+    /// Size of an archived capture on disk
+    fn stored_size(&self, id: &str) -> u64 {
+        fs::metadata(self.output_file(id))
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
+    /// Apply the retention rules, removing the oldest captures first
+    ///
+    /// Pinned captures are always kept and do not count towards the limits.
+    /// Returns the number of captures removed.
+    pub fn apply_retention(&self, settings: &ArchiveSettings) -> Result<usize> {
+        if !self.root.exists() {
+            return Ok(0);
+        }
+        let _lock = self.lock()?;
+        let (pinned, entries): (Vec<_>, Vec<_>) =
+            self.entries()?.into_iter().partition(|e| e.pinned);
+        let mut expired = Vec::new();
+        let mut kept = Vec::new();
+
+        // Age
+        let now = Local::now();
+        for entry in entries {
+            let age_days = (now - entry.started_at).num_days();
+            if settings.max_age_days > 0 && age_days >= settings.max_age_days as i64 {
+                expired.push(entry);
+            } else {
+                kept.push(entry);
+            }
+        }
+
+        // Count
+        if settings.max_count > 0 && kept.len() > settings.max_count {
+            let excess = kept.len() - settings.max_count;
+            expired.extend(kept.drain(..excess));
+        }
+
+        // Total size
+        if settings.max_size_mb > 0 {
+            let limit = settings.max_size_mb * 1024 * 1024;
+            let mut total: u64 = kept.iter().map(|e| self.stored_size(&e.id)).sum();
+            while total > limit && !kept.is_empty() {
+                let entry = kept.remove(0);
+                total -= self.stored_size(&entry.id);
+                expired.push(entry);
+            }
+        }
+
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        kept.extend(pinned);
+        kept.sort_by_key(|e| e.started_at);
+        self.save_entries(&kept)?;
+        for entry in &expired {
+            let output = self.output_file(&entry.id);
+            if output.exists() {
+                fs::remove_file(output)?;
+            }
+        }
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    fn meta(command: &str, days_ago: i64) -> CaptureMeta {
+        let mut meta = CaptureMeta::new(command, "s1", 1);
+        meta.started_at = Local::now() - Duration::days(days_ago);
+        meta.finish(Some(0), 0);
+        meta
+    }
+
+    #[test]
+    fn test_add_and_read() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().join("archive"));
+
+        let entry = meta("cargo build", 0);
+        archive.add(&entry, b"compiled").unwrap();
+
+        assert_eq!(archive.entries().unwrap(), vec![entry.clone()]);
+        assert_eq!(
+            fs::read(archive.output_file(&entry.id)).unwrap(),
+            b"compiled"
+        );
+    }
+
+    #[test]
+    fn test_add_refuses_existing_id() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        let entry = meta("make", 0);
+        archive.add(&entry, b"first").unwrap();
+        let other = CaptureMeta {
+            command: "rm -rf build".to_string(),
+            ..entry.clone()
+        };
+        assert!(archive.add(&other, b"second").is_err());
+
+        // The archived capture is untouched
+        assert_eq!(archive.entries().unwrap(), vec![entry.clone()]);
+        assert_eq!(fs::read(archive.output_file(&entry.id)).unwrap(), b"first");
+    }
+
+    #[test]
+    fn test_entries_sorted() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        let new = meta("new", 0);
+        let old = meta("old", 2);
+        archive.add(&new, b"").unwrap();
+        archive.add(&old, b"").unwrap();
+
+        let commands: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["old", "new"]);
+    }
+
+    #[test]
+    fn test_update_and_remove() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        let mut entry = meta("ls", 0);
+        archive.add(&entry, b"files").unwrap();
+
+        entry.pinned = true;
+        assert!(archive.update(&entry).unwrap());
+        assert!(archive.entries().unwrap()[0].pinned);
+
+        assert!(archive.remove(&entry.id).unwrap());
+        assert!(!archive.remove(&entry.id).unwrap());
+        assert!(!archive.update(&entry).unwrap());
+        assert!(archive.entries().unwrap().is_empty());
+        assert!(!archive.output_file(&entry.id).exists());
+    }
+
+    #[test]
+    fn test_concurrent_adds() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().join("archive"));
+
+        let added: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|i| {
+                    let archive = &archive;
+                    scope.spawn(move || {
+                        (0..4)
+                            .map(|_| {
+                                let entry = meta(&format!("worker {}", i), 0);
+                                archive.add(&entry, b"").unwrap();
+                                entry.id
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
+
+        // No add is lost to another shell rewriting the index
+        let entries = archive.entries().unwrap();
+        assert_eq!(entries.len(), added.len());
+        assert!(added.iter().all(|id| entries.iter().any(|e| &e.id == id)));
+    }
+
+    #[test]
+    fn test_retention_keeps_pinned() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        let mut old = meta("old", 40);
+        old.pinned = true;
+        archive.add(&old, b"x").unwrap();
+        archive.add(&meta("new", 0), b"x").unwrap();
+
+        let settings = ArchiveSettings {
+            max_age_days: 30,
+            max_count: 1,
+            ..ArchiveSettings::default()
+        };
+        assert_eq!(archive.apply_retention(&settings).unwrap(), 0);
+        assert_eq!(archive.entries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_retention_by_age_and_count() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        for days_ago in [40, 3, 2, 1, 0] {
+            archive
+                .add(&meta(&format!("cmd{}", days_ago), days_ago), b"x")
+                .unwrap();
+        }
+
+        let settings = ArchiveSettings {
+            max_age_days: 30,
+            max_count: 3,
+            max_size_mb: 0,
+            ..ArchiveSettings::default()
+        };
+        assert_eq!(archive.apply_retention(&settings).unwrap(), 2);
+
+        let commands: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["cmd2", "cmd1", "cmd0"]);
+    }
+
+    #[test]
+    fn test_retention_by_size() {
+        let dir = tempdir().unwrap();
+        let archive = Archive::new(dir.path().to_path_buf());
+
+        let big = vec![b'x'; 700 * 1024];
+        let old = meta("old", 1);
+        let new = meta("new", 0);
+        archive.add(&old, &big).unwrap();
+        archive.add(&new, &big).unwrap();
+
+        let settings = ArchiveSettings {
+            max_size_mb: 1,
+            ..ArchiveSettings::default()
+        };
+        assert_eq!(archive.apply_retention(&settings).unwrap(), 1);
+        assert_eq!(archive.entries().unwrap(), vec![new]);
+    }
+}
@@ -0,0 +1,638 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use regex::Regex;
+use std::path::PathBuf;
+
+use crate::clipboard::Clipboard;
+use crate::compress::Compression;
+use crate::config::Config;
+use crate::error::Result;
+use crate::export::{self, Log, LogFormat};
+use crate::search::{self, PatternOptions};
+use crate::store::{CaptureRecord, CaptureStore};
+
+/// Key binding help shown in the status bar
+const HELP: &str = "q quit  j/k select  J/K PgUp/PgDn g/G scroll  / search  n/N next  s save  y copy  d delete  p pin  r rerun";
+
+/// What the caller should do once the browser exits
+#[derive(Debug, Clone)]
+pub enum BrowseOutcome {
+    /// Nothing, the user quit
+    Quit,
+
+    /// Re-run the command of this capture
+    Rerun(Box<CaptureRecord>),
+}
+
+/// Input mode of the browser
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    /// Navigating
+    Normal,
+
+    /// Typing a search pattern
+    Search(String),
+
+    /// Waiting for confirmation to delete the selected capture
+    ConfirmDelete,
+}
+
+/// Copies text somewhere, usually the system clipboard, and says where
+type Copier = Box<dyn Fn(&str) -> Result<String>>;
+
+/// Full-screen browser over the capture store
+pub struct Browser<'s> {
+    /// Capture store
+    store: CaptureStore<'s>,
+
+    /// All captures, newest first
+    records: Vec<CaptureRecord>,
+
+    /// Selection in the capture list
+    list_state: ListState,
+
+    /// Id of the capture whose output is loaded
+    loaded: Option<String>,
+
+    /// Cleaned output lines of the selected capture
+    lines: Vec<String>,
+
+    /// First visible output line
+    scroll: usize,
+
+    /// Height of the output pane, updated on every draw
+    page: usize,
+
+    /// Active search pattern
+    search: Option<Regex>,
+
+    /// Current input mode
+    mode: Mode,
+
+    /// Message shown in the status bar
+    status: Option<String>,
+
+    /// Directory where saved logs are written
+    save_dir: PathBuf,
+
+    /// Clipboard implementation
+    copier: Copier,
+
+    /// Set when the browser should exit
+    outcome: Option<BrowseOutcome>,
+}
+
+impl<'s> Browser<'s> {
+    /// Create a browser over all captures known to the config
+    pub fn new(config: &'s Config) -> Result<Self> {
+        let mut browser = Self {
+            store: CaptureStore::new(config),
+            records: Vec::new(),
+            list_state: ListState::default(),
+            loaded: None,
+            lines: Vec::new(),
+            scroll: 0,
+            page: 1,
+            search: None,
+            mode: Mode::Normal,
+            status: None,
+            save_dir: PathBuf::from("."),
+            copier: {
+                let clipboard = Clipboard::new(config);
+                Box::new(move |text| clipboard.copy(text))
+            },
+            outcome: None,
+        };
+        browser.reload()?;
+        Ok(browser)
+    }
+
+    /// Write saved logs to this directory instead of the current one
+    #[cfg(test)]
+    pub fn with_save_dir(mut self, dir: PathBuf) -> Self {
+        self.save_dir = dir;
+        self
+    }
+
+    /// Use this copier instead of the system clipboard
+    #[cfg(test)]
+    pub fn with_copier(mut self, copier: impl Fn(&str) -> Result<String> + 'static) -> Self {
+        self.copier = Box::new(copier);
+        self
+    }
+
+    /// Why the browser exited, once it has
+    #[cfg(test)]
+    pub fn outcome(&self) -> Option<&BrowseOutcome> {
+        self.outcome.as_ref()
+    }
+
+    /// Reload the capture list, keeping the selection in place
+    fn reload(&mut self) -> Result<()> {
+        self.records = self.store.list()?;
+        let selected = match self.list_state.selected() {
+            _ if self.records.is_empty() => None,
+            Some(index) => Some(index.min(self.records.len() - 1)),
+            None => Some(0),
+        };
+        self.list_state.select(selected);
+        self.load_selected();
+        Ok(())
+    }
+
+    /// The selected capture
+    fn selected(&self) -> Option<&CaptureRecord> {
+        self.list_state.selected().and_then(|i| self.records.get(i))
+    }
+
+    /// Load the output of the selected capture if it changed
+    fn load_selected(&mut self) {
+        let id = self.selected().map(|r| r.meta.id.clone());
+        if id == self.loaded {
+            return;
+        }
+
+        self.lines = match self.selected().map(|r| r.read_output()) {
+            Some(Ok(content)) => String::from_utf8_lossy(&strip_ansi_escapes::strip(&content))
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            Some(Err(e)) => vec![format!("<{}>", e)],
+            None => Vec::new(),
+        };
+        self.loaded = id;
+        self.scroll = 0;
+    }
+
+    /// Move the selection by `delta` captures
+    fn select(&mut self, delta: isize) {
+        if self.records.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.records.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+        self.load_selected();
+    }
+
+    /// Scroll the output pane by `delta` lines
+    fn scroll_by(&mut self, delta: isize) {
+        let max = self.lines.len().saturating_sub(self.page) as isize;
+        self.scroll = (self.scroll as isize + delta).clamp(0, max.max(0)) as usize;
+    }
+
+    /// Scroll to the next (or previous) line matching the search
+    fn jump_to_match(&mut self, forward: bool) {
+        let Some(regex) = &self.search else {
+            return;
+        };
+        let found = if forward {
+            (self.scroll + 1..self.lines.len()).find(|&i| regex.is_match(&self.lines[i]))
+        } else {
+            (0..self.scroll)
+                .rev()
+                .find(|&i| regex.is_match(&self.lines[i]))
+        };
+
+        match found {
+            Some(line) => {
+                self.scroll = line;
+                self.status = None;
+            }
+            None => self.status = Some("No more matches".to_string()),
+        }
+    }
+
+    /// Handle a key press
+    ///
+    /// Failures of actions are reported in the status bar.
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        let result = match self.mode.clone() {
+            Mode::Search(input) => {
+                self.handle_search_key(key, input);
+                Ok(())
+            }
+            Mode::ConfirmDelete => {
+                self.mode = Mode::Normal;
+                match key.code {
+                    KeyCode::Char('y') => self.delete_selected(),
+                    _ => {
+                        self.status = Some("Delete cancelled".to_string());
+                        Ok(())
+                    }
+                }
+            }
+            Mode::Normal => self.handle_normal_key(key),
+        };
+
+        if let Err(e) = result {
+            self.status = Some(format!("Error: {}", e));
+        }
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent, mut input: String) {
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Enter => {
+                self.mode = Mode::Normal;
+                // Smart case: only case-sensitive if the pattern has capitals
+                let options = PatternOptions {
+                    ignore_case: !input.chars().any(char::is_uppercase),
+                    ..PatternOptions::default()
+                };
+                match search::build_pattern(&input, options) {
+                    Ok(regex) => {
+                        self.search = Some(regex);
+                        if self.search_matches(self.scroll) {
+                            self.status = None;
+                        } else {
+                            self.jump_to_match(true);
+                        }
+                    }
+                    Err(e) => self.status = Some(e.to_string()),
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                self.mode = Mode::Search(input);
+            }
+            KeyCode::Char(c) => {
+                input.push(c);
+                self.mode = Mode::Search(input);
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a line matches the active search
+    fn search_matches(&self, line: usize) -> bool {
+        match (&self.search, self.lines.get(line)) {
+            (Some(regex), Some(text)) => regex.is_match(text),
+            _ => false,
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) -> Result<()> {
+        let page = self.page as isize;
+        self.status = None;
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.outcome = Some(BrowseOutcome::Quit),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.outcome = Some(BrowseOutcome::Quit)
+            }
+            KeyCode::Char('j') | KeyCode::Down => self.select(1),
+            KeyCode::Char('k') | KeyCode::Up => self.select(-1),
+            KeyCode::Char('J') => self.scroll_by(1),
+            KeyCode::Char('K') => self.scroll_by(-1),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_by(page),
+            KeyCode::PageUp => self.scroll_by(-page),
+            KeyCode::Char('g') | KeyCode::Home => self.scroll = 0,
+            KeyCode::Char('G') | KeyCode::End => self.scroll_by(isize::MAX / 2),
+            KeyCode::Char('/') => self.mode = Mode::Search(String::new()),
+            KeyCode::Char('n') => self.jump_to_match(true),
+            KeyCode::Char('N') => self.jump_to_match(false),
+            KeyCode::Char('s') => self.save_selected()?,
+            KeyCode::Char('y') => self.copy_selected()?,
+            KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+            KeyCode::Char('p') => self.toggle_pin()?,
+            KeyCode::Char('r') => {
+                if let Some(record) = self.selected() {
+                    self.outcome = Some(BrowseOutcome::Rerun(Box::new(record.clone())));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn save_selected(&mut self) -> Result<()> {
+        let Some(record) = self.selected() else {
+            return Ok(());
+        };
+        let path = self
+            .save_dir
+            .join(export::default_log_name(LogFormat::Text, Compression::None))
+            .display()
+            .to_string();
+        let output = self.lines.join("\n");
+        export::save_log(
+            &path,
+            &Log::new(&record.meta.command, &output),
+            LogFormat::Text,
+            Compression::None,
+        )?;
+        self.status = Some(format!("Saved to {}", path));
+        Ok(())
+    }
+
+    fn copy_selected(&mut self) -> Result<()> {
+        if self.selected().is_none() {
+            return Ok(());
+        }
+        let target = (self.copier)(&self.lines.join("\n"))?;
+        self.status = Some(format!("Copied to {}", target));
+        Ok(())
+    }
+
+    fn delete_selected(&mut self) -> Result<()> {
+        let Some(record) = self.selected().cloned() else {
+            return Ok(());
+        };
+        self.store.remove(&record)?;
+        self.reload()?;
+        self.status = Some(format!("Deleted {}", record.meta.short_id()));
+        Ok(())
+    }
+
+    fn toggle_pin(&mut self) -> Result<()> {
+        let Some(index) = self.list_state.selected() else {
+            return Ok(());
+        };
+        let record = &mut self.records[index];
+        let pinned = !record.meta.pinned;
+        self.store.set_pinned(record, pinned)?;
+        self.status = Some(if pinned { "Pinned" } else { "Unpinned" }.to_string());
+        Ok(())
+    }
+
+    /// Render the browser
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [main, status_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, output_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        // Capture list
+        let items: Vec<ListItem> = self
+            .records
+            .iter()
+            .map(|record| {
+                let meta = &record.meta;
+                let (mark, color) = match meta.exit_code {
+                    Some(0) => ("✔", Color::Green),
+                    Some(_) => ("✘", Color::Red),
+                    None => ("…", Color::Yellow),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(if meta.pinned { "★ " } else { "  " }),
+                    Span::styled(mark, Style::default().fg(color)),
+                    Span::styled(
+                        format!(" {} ", meta.started_at.format("%m-%d %H:%M")),
+                        Style::default().add_modifier(Modifier::DIM),
+                    ),
+                    Span::raw(meta.command.clone()),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" Captures ({}) ", self.records.len())),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list_state);
+
+        // Output pane
+        self.page = output_area.height.saturating_sub(2).max(1) as usize;
+        let title = match self.selected() {
+            Some(record) => format!(" {} ", record.meta.command),
+            None => " No captures ".to_string(),
+        };
+        let visible: Vec<Line> = self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(self.page)
+            .map(|(i, text)| {
+                if self.search_matches(i) {
+                    Line::styled(
+                        text.clone(),
+                        Style::default().fg(Color::Black).bg(Color::Yellow),
+                    )
+                } else {
+                    Line::raw(text.clone())
+                }
+            })
+            .collect();
+        let position = if self.lines.is_empty() {
+            String::new()
+        } else {
+            format!(" {}/{} ", self.scroll + 1, self.lines.len())
+        };
+        let output = Paragraph::new(visible).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .title_bottom(Line::from(position).right_aligned()),
+        );
+        frame.render_widget(output, output_area);
+
+        // Status bar
+        let status = match &self.mode {
+            Mode::Search(input) => format!("/{}", input),
+            Mode::ConfirmDelete => match self.selected() {
+                Some(record) => format!("Delete capture {}? (y/n)", record.meta.short_id()),
+                None => String::new(),
+            },
+            Mode::Normal => self.status.clone().unwrap_or_else(|| HELP.to_string()),
+        };
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().add_modifier(Modifier::DIM)),
+            status_area,
+        );
+    }
+
+    /// Run the event loop until the user quits
+    pub fn run(mut self, terminal: &mut DefaultTerminal) -> Result<BrowseOutcome> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                self.handle_key(key);
+            }
+            if let Some(outcome) = self.outcome.take() {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+/// Open the browser on the terminal
+pub fn browse(config: &Config) -> Result<BrowseOutcome> {
+    let browser = Browser::new(config)?;
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_utils::{create_test_config_with_tempdir, run_command};
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    fn press(browser: &mut Browser, code: KeyCode) {
+        browser.handle_key(KeyEvent::from(code));
+    }
+
+    fn type_text(browser: &mut Browser, text: &str) {
+        for c in text.chars() {
+            press(browser, KeyCode::Char(c));
+        }
+    }
+
+    fn render(browser: &mut Browser) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| browser.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_renders_list_and_output() {
+        let (config, _temp_dir) = create_test_config_with_tempdir();
+        run_command(&config, "cargo build", "error: boom", 101);
+        run_command(&config, "ls", "Cargo.toml", 0);
+
+        let mut browser = Browser::new(&config).unwrap();
+        let screen = render(&mut browser);
+        assert!(screen.contains("Captures (2)"));
+        assert!(screen.contains("cargo build"));
+        assert!(screen.contains("Cargo.toml"));
+
+        press(&mut browser, KeyCode::Char('j'));
+        let screen = render(&mut browser);
+        assert!(screen.contains("error: boom"));
+    }
+
+    #[test]
+    fn test_search_scrolls_to_match() {
+        let (config, _temp_dir) = create_test_config_with_tempdir();
+        let output: Vec<String> = (1..=100).map(|i| format!("line {}", i)).collect();
+        run_command(&config, "seq", &output.join("\n"), 0);
+
+        let mut browser = Browser::new(&config).unwrap();
+        render(&mut browser);
+
+        press(&mut browser, KeyCode::Char('/'));
+        type_text(&mut browser, "line 7\\d");
+        assert!(render(&mut browser).contains("/line 7\\d"));
+        press(&mut browser, KeyCode::Enter);
+        assert_eq!(browser.scroll, 69);
+
+        press(&mut browser, KeyCode::Char('n'));
+        assert_eq!(browser.scroll, 70);
+        press(&mut browser, KeyCode::Char('N'));
+        assert_eq!(browser.scroll, 69);
+    }
+
+    #[test]
+    fn test_actions() {
+        let (config, temp_dir) = create_test_config_with_tempdir();
+        run_command(&config, "make", "built", 0);
+
+        let copied = Rc::new(RefCell::new(String::new()));
+        let sink = copied.clone();
+        let mut browser = Browser::new(&config)
+            .unwrap()
+            .with_save_dir(temp_dir.path().to_path_buf())
+            .with_copier(move |text| {
+                *sink.borrow_mut() = text.to_string();
+                Ok("clipboard".to_string())
+            });
+
+        // Copy
+        press(&mut browser, KeyCode::Char('y'));
+        assert_eq!(*copied.borrow(), "built");
+
+        // Save
+        press(&mut browser, KeyCode::Char('s'));
+        let saved = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .flatten()
+            .find(|e| e.file_name().to_string_lossy().starts_with("tellme_"))
+            .expect("log not saved");
+        assert!(fs::read_to_string(saved.path()).unwrap().contains("built"));
+
+        // Pin
+        press(&mut browser, KeyCode::Char('p'));
+        let store = CaptureStore::new(&config);
+        assert!(store.list().unwrap()[0].meta.pinned);
+        assert!(render(&mut browser).contains("★"));
+
+        // Re-run
+        press(&mut browser, KeyCode::Char('r'));
+        match browser.outcome() {
+            Some(BrowseOutcome::Rerun(record)) => assert_eq!(record.meta.command, "make"),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delete_asks_for_confirmation() {
+        let (config, _temp_dir) = create_test_config_with_tempdir();
+        run_command(&config, "make", "built", 0);
+        run_command(&config, "ls", "files", 0);
+
+        let mut browser = Browser::new(&config).unwrap();
+        assert_eq!(browser.records.len(), 2);
+
+        press(&mut browser, KeyCode::Char('d'));
+        assert!(render(&mut browser).contains("? (y/n)"));
+        press(&mut browser, KeyCode::Char('n'));
+        assert_eq!(browser.records.len(), 2);
+
+        press(&mut browser, KeyCode::Char('j'));
+        press(&mut browser, KeyCode::Char('d'));
+        press(&mut browser, KeyCode::Char('y'));
+        let commands: Vec<_> = browser
+            .records
+            .iter()
+            .map(|r| r.meta.command.as_str())
+            .collect();
+        assert_eq!(commands, vec!["ls"]);
+    }
+
+    #[test]
+    fn test_deleted_latest_capture_stays_deleted() {
+        let (config, _temp_dir) = create_test_config_with_tempdir();
+        run_command(&config, "make", "built", 0);
+        run_command(&config, "ls", "files", 0);
+
+        let mut browser = Browser::new(&config).unwrap();
+        assert_eq!(browser.records[0].meta.command, "ls");
+        press(&mut browser, KeyCode::Char('d'));
+        press(&mut browser, KeyCode::Char('y'));
+
+        // The shell's live files held it too, and must not bring it back
+        browser.reload().unwrap();
+        let commands: Vec<_> = browser
+            .records
+            .iter()
+            .map(|r| r.meta.command.as_str())
+            .collect();
+        assert_eq!(commands, vec!["make"]);
+        assert_eq!(Browser::new(&config).unwrap().records.len(), 1);
+    }
+}
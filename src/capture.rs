@@ -1,7 +1,51 @@
+use crate::archive::Archive;
+use crate::compress::{self, Compression};
 use crate::config::Config;
+use crate::error::{Result, TellmeError};
 use crate::filter::CommandFilter;
+use crate::gc;
+use crate::lock::FileLock;
+use crate::meta::CaptureMeta;
+use crate::process;
+use crate::secure;
+use crate::settings::ArchiveSettings;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often to poll the output file while waiting for the writer to finish
+const SETTLE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Upper bound on how long to wait for the writer to finish
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Contents of the completion marker of a capture
+///
+/// Written last, once the command, output and metadata of a capture are all
+/// in place, so a reader can tell they belong together.
+#[derive(Debug, Serialize, Deserialize)]
+struct CompletionMarker {
+    /// Id of the completed capture
+    id: String,
+
+    /// Size of the output file, as stored, when the capture completed
+    size: u64,
+
+    /// Whether the capture was also kept in the history or archive
+    #[serde(default)]
+    kept: bool,
+}
+
+impl CompletionMarker {
+    fn new(meta: &CaptureMeta, size: u64, kept: bool) -> Self {
+        Self {
+            id: meta.id.clone(),
+            size,
+            kept,
+        }
+    }
+}
 
 /// Manages temporary output capture files
 ///
@@ -22,13 +66,13 @@ impl<'s> CaptureSession<'s> {
     /// Create command file
     ///
     /// Returns the path to the command file.
-    fn create_cmd_file(&self, command: &str) -> anyhow::Result<PathBuf> {
+    fn create_cmd_file(&self, command: &str) -> Result<PathBuf> {
         let path = self.config.cmd_file();
         // Ensure the parent directory exists
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+            secure::private_dir(parent)?;
         }
-        fs::write(&path, command)?;
+        secure::write_atomic(&path, command)?;
 
         Ok(path)
     }
@@ -36,25 +80,124 @@ impl<'s> CaptureSession<'s> {
     /// Create output file
     ///
     /// Returns the path to the output file.
-    fn create_output_file(&self) -> anyhow::Result<PathBuf> {
+    fn create_output_file(&self) -> Result<PathBuf> {
         let path = self.config.output_file();
         // Ensure the parent directory exists
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+            secure::private_dir(parent)?;
         }
-        fs::File::create(&path)?;
+        secure::create_private(&path)?;
 
         Ok(path)
     }
 
+    /// Write capture metadata
+    fn write_meta(&self, meta: &CaptureMeta) -> Result<()> {
+        secure::write_atomic(&self.config.meta_file(), serde_json::to_vec(meta)?)
+    }
+
+    /// Mark the current capture as completely written
+    fn write_done(&self, marker: &CompletionMarker) -> Result<()> {
+        secure::write_atomic(&self.config.done_file(), serde_json::to_vec(marker)?)
+    }
+
+    /// Withdraw the completion marker before the files are rewritten
+    fn clear_done(&self) -> Result<()> {
+        match fs::remove_file(self.config.done_file()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the completion marker of the current capture
+    fn read_done(&self) -> Result<Option<CompletionMarker>> {
+        match fs::read(self.config.done_file()) {
+            Ok(data) => Ok(serde_json::from_slice(&data).ok()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Take this shell's capture lock, so files are written and read as a unit
+    fn lock(&self, exclusive: bool) -> Result<FileLock> {
+        secure::private_dir(self.config.temp_path())?;
+        let path = self.config.lock_file();
+        if exclusive {
+            FileLock::exclusive(&path)
+        } else {
+            FileLock::shared(&path)
+        }
+    }
+
+    /// Read the metadata of the last capture
+    ///
+    /// Returns `None` for captures prepared before metadata was recorded.
+    pub fn read_meta(&self) -> Result<Option<CaptureMeta>> {
+        match fs::read(self.config.meta_file()) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Read the command
-    pub fn read_cmd_file(&self) -> anyhow::Result<String> {
-        fs::read_to_string(&self.config.cmd_file()).map_err(|e| anyhow::anyhow!(e))
+    pub fn read_cmd_file(&self) -> Result<String> {
+        fs::read_to_string(self.config.cmd_file())
+            .map_err(TellmeError::io("Cannot read the last command"))
     }
 
-    /// Read the last captured command output
-    pub fn read_output(&self) -> anyhow::Result<Vec<u8>> {
-        fs::read(&self.config.output_file()).map_err(|e| anyhow::anyhow!(e))
+    /// Read the command and output of the last completed capture
+    ///
+    /// Refuses files that do not belong to the same capture, e.g. while
+    /// another command of this shell is being prepared. Output written after
+    /// the command finished, such as by a background job, is left out.
+    #[allow(dead_code, reason = "library API, unused by the binary")]
+    pub fn read_last(&self) -> Result<(String, Vec<u8>)> {
+        self.read_last_capture()
+            .map(|(meta, output)| (meta.command, output))
+    }
+
+    /// Read the metadata and output of the last completed capture
+    ///
+    /// Same checks as [`CaptureSession::read_last`].
+    pub fn read_last_capture(&self) -> Result<(CaptureMeta, Vec<u8>)> {
+        let _lock = self.lock(false)?;
+        if !self.has_previous() {
+            return Err(TellmeError::NoPreviousCapture);
+        }
+
+        let command = self.read_cmd_file()?;
+        let meta = self.read_meta()?;
+        let done = self.read_done()?;
+        let mut data = fs::read(self.config.output_file())
+            .map_err(TellmeError::io("Cannot read the last output"))?;
+
+        let meta = match (meta, done) {
+            (Some(meta), Some(done)) if done.id == meta.id && meta.command == command => {
+                if (data.len() as u64) < done.size {
+                    return Err(TellmeError::IncompleteCapture(
+                        "The output of the last command was overwritten".into(),
+                    ));
+                }
+                data.truncate(done.size as usize);
+                meta
+            }
+            (Some(meta), _) if !meta.is_complete() => {
+                return Err(TellmeError::IncompleteCapture(format!(
+                    "The last command '{}' has not finished yet",
+                    meta.command
+                )));
+            }
+            _ => {
+                return Err(TellmeError::IncompleteCapture(
+                    "The command and output files of this shell belong to different captures"
+                        .into(),
+                ));
+            }
+        };
+
+        let output = compress::decompress(data, meta.compression)?;
+        Ok((meta, output))
     }
 
     /// Check if there's a previous command to capture
@@ -77,30 +220,183 @@ impl<'s> CaptureSession<'s> {
     ///
     /// This handles:
     /// 1. Creating new current files
-    /// 2. Returning paths for the shell to use
-    pub fn prepare_new_command(&self, command: &str) -> anyhow::Result<PathBuf> {
+    /// 2. Recording the capture metadata
+    /// 3. Returning paths for the shell to use
+    pub fn prepare_new_command(&self, command: &str) -> Result<PathBuf> {
+        let _lock = self.lock(true)?;
+        gc::claim_shell(self.config)?;
+        // The background step of the last command may not have run yet
+        self.store_pending()?;
+
         // Create new files
+        self.clear_done()?;
         self.create_cmd_file(command)?;
         self.create_output_file()?;
+        let mut meta = CaptureMeta::new(command, self.config.session(), self.config.pid());
+        meta.capture_env(&self.config.settings().capture.env);
+        self.write_meta(&meta)?;
 
         Ok(self.config.output_file())
     }
 
-    /// Clean up all temporary files for this session
-    pub fn cleanup(&self) -> anyhow::Result<()> {
-        for file in self.config.temp_files() {
-            if file.exists() {
-                fs::remove_file(file)?;
+    /// Finish the current command
+    ///
+    /// Called by the shell once the command has completed, so it only
+    /// records the exit code and duration and marks the capture complete.
+    /// Compressing and keeping the output is left to [`CaptureSession::store_last`],
+    /// run in the background by [`spawn_store`].
+    /// Returns the finished capture, or `None` if there was nothing to finish.
+    pub fn finish_command(&self, exit_code: Option<i32>) -> Result<Option<CaptureMeta>> {
+        let _lock = self.lock(true)?;
+        let path = self.config.output_file();
+        let Ok(size) = fs::metadata(&path).map(|m| m.len()) else {
+            return Ok(None);
+        };
+
+        let Some(mut meta) = self.read_meta()? else {
+            return Ok(None);
+        };
+        if meta.is_complete() {
+            return Ok(None);
+        }
+
+        // The file holds the output exactly as the shell wrote it
+        meta.compression = Some(Compression::None);
+        meta.finish(exit_code, size);
+        self.write_meta(&meta)?;
+        self.write_done(&CompletionMarker::new(&meta, size, false))?;
+        Ok(Some(meta))
+    }
+
+    /// Compress and keep the last finished capture, unless already kept
+    ///
+    /// Waits for the `tee` process, which may still be flushing after the
+    /// shell restored its fds, so the whole output is kept.
+    pub fn store_last(&self) -> Result<()> {
+        wait_for_writer(&self.config.output_file());
+        let _lock = self.lock(true)?;
+        self.store_pending()
+    }
+
+    /// Keep the finished capture in the shell's files if it was not kept yet
+    ///
+    /// The caller holds the exclusive lock.
+    fn store_pending(&self) -> Result<()> {
+        let (Some(mut meta), Some(done)) = (self.read_meta()?, self.read_done()?) else {
+            return Ok(());
+        };
+        if done.id != meta.id || done.kept {
+            return Ok(());
+        }
+
+        let path = self.config.output_file();
+        let mut data = fs::read(&path)?;
+        let compression = self.config.settings().capture.compression;
+        if meta.compression == Some(Compression::None) {
+            // Include what the writer flushed after the command finished
+            meta.size = data.len() as u64;
+            if compression != Compression::None {
+                data = compression.compress(&data)?;
+                // A `tee` still writing keeps its own copy instead of corrupting this one
+                secure::write_atomic(&path, &data)?;
+                meta.compression = Some(compression);
             }
+            self.write_meta(&meta)?;
+        } else {
+            data.truncate(done.size as usize);
+        }
+
+        // Kept already if interrupted after that, as the archive refuses duplicates
+        if !self.kept_in().output_file(&meta.id).exists() {
+            self.store_completed(&meta, &data)?;
         }
+        self.write_done(&CompletionMarker::new(&meta, data.len() as u64, true))
+    }
+
+    /// Record a command run by tellme itself as the shell's last capture
+    ///
+    /// `meta` must already be finished; `output` is the raw output.
+    pub fn record(&self, meta: &CaptureMeta, output: &[u8]) -> Result<()> {
+        let compression = self.config.settings().capture.compression;
+        let data = compression.compress(output)?;
+        let meta = &CaptureMeta {
+            compression: Some(compression),
+            ..meta.clone()
+        };
+
+        let _lock = self.lock(true)?;
+        gc::claim_shell(self.config)?;
+        self.store_pending()?;
+        self.clear_done()?;
+        self.create_cmd_file(&meta.command)?;
+        secure::write_atomic(&self.config.output_file(), &data)?;
+        self.write_meta(meta)?;
+        self.store_completed(meta, &data)?;
+        self.write_done(&CompletionMarker::new(meta, data.len() as u64, true))
+    }
+
+    /// Where completed captures are kept
+    fn kept_in(&self) -> Archive {
+        if self.config.settings().archive.enabled {
+            Archive::new(self.config.archive_dir())
+        } else {
+            Archive::new(self.config.history_dir())
+        }
+    }
+
+    /// Keep a completed capture: persistently if archiving, otherwise for this shell only
+    fn store_completed(&self, meta: &CaptureMeta, data: &[u8]) -> Result<()> {
+        let archive_settings = &self.config.settings().archive;
+        let kept_in = self.kept_in();
+        kept_in.add(meta, data)?;
+        if archive_settings.enabled {
+            kept_in.apply_retention(archive_settings)?;
+        } else {
+            kept_in.apply_retention(&ArchiveSettings {
+                max_age_days: 0,
+                max_count: self.config.settings().capture.history,
+                max_size_mb: 0,
+                ..ArchiveSettings::default()
+            })?;
+        }
+
         Ok(())
     }
+
+    /// Clean up all temporary files for this session
+    pub fn cleanup(&self) -> Result<()> {
+        gc::remove_shell_files(self.config, self.config.session())
+    }
+}
+
+/// Start a detached `tellme internal --store` for the capture just finished
+pub fn spawn_store() -> Result<()> {
+    process::spawn_detached(&["internal", "--store"], b"")
+}
+
+/// Wait until the size of a file stops changing
+fn wait_for_writer(path: &Path) {
+    let start = Instant::now();
+    let size = |p: &Path| fs::metadata(p).map(|m| m.len()).ok();
+    let mut last = size(path);
+
+    while start.elapsed() < SETTLE_TIMEOUT {
+        std::thread::sleep(SETTLE_INTERVAL);
+        let current = size(path);
+        if current == last {
+            break;
+        }
+        last = current;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::test_utils::{create_test_config, create_test_config_with_tempdir};
+    use crate::config::test_utils::{
+        create_test_config, create_test_config_with_tempdir, run_command,
+    };
+    use crate::settings::Settings;
 
     #[test]
     fn test_create_cmd_file() {
@@ -147,19 +443,59 @@ mod tests {
     }
 
     #[test]
-    fn test_read_cmd_file() {
+    fn test_read_last() {
         let config = create_test_config();
         let session = CaptureSession::new(&config);
 
-        // Create current files and write content
-        session.create_cmd_file("last cmd").unwrap();
+        session.prepare_new_command("last cmd").unwrap();
         fs::write(config.output_file(), b"last output").unwrap();
+        let error = session.read_last().unwrap_err().to_string();
+        assert!(error.contains("has not finished"), "{}", error);
 
-        // Read last log and command
-        let last_cmd = session.read_cmd_file().unwrap();
-        let last_output = session.read_output().unwrap();
+        session.finish_command(Some(0)).unwrap();
+        let (last_cmd, last_output) = session.read_last().unwrap();
         assert_eq!(last_cmd, "last cmd");
         assert_eq!(last_output, b"last output");
+
+        // A background job writing after the command finished is left out
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(config.output_file())
+            .unwrap();
+        std::io::Write::write_all(&mut file, b" and more").unwrap();
+        assert_eq!(session.read_last().unwrap().1, b"last output");
+    }
+
+    #[test]
+    fn test_read_last_refuses_mismatched_files() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+
+        session.prepare_new_command("make").unwrap();
+        fs::write(config.output_file(), b"built").unwrap();
+        session.finish_command(Some(0)).unwrap();
+
+        // Another capture of the same shell rewrote the command file only
+        session.create_cmd_file("ls").unwrap();
+        let error = session.read_last().unwrap_err().to_string();
+        assert!(error.contains("different captures"), "{}", error);
+
+        // A capture that is being prepared has no completion marker yet
+        session.create_cmd_file("make").unwrap();
+        session.clear_done().unwrap();
+        assert!(session.read_last().is_err());
+
+        // The output was truncated after the capture completed
+        session.finish_command(Some(0)).unwrap();
+        session
+            .write_done(&CompletionMarker::new(
+                &session.read_meta().unwrap().unwrap(),
+                b"built twice".len() as u64,
+                false,
+            ))
+            .unwrap();
+        let error = session.read_last().unwrap_err().to_string();
+        assert!(error.contains("overwritten"), "{}", error);
     }
 
     #[test]
@@ -193,4 +529,174 @@ mod tests {
         let cmd_content = std::fs::read_to_string(config.cmd_file()).unwrap();
         assert_eq!(cmd_content, "cargo test");
     }
+
+    #[test]
+    fn test_finish_command_compresses() {
+        let (_, temp_dir) = create_test_config_with_tempdir();
+        let config_dir = temp_dir.path().join("my_tellme_config");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join(Settings::FILE_NAME),
+            "[capture]\ncompression = \"gzip\"\n",
+        )
+        .unwrap();
+        let config = Config::with_paths(
+            Some("99999".to_string()),
+            Some(99999),
+            Some(config_dir),
+            Some(temp_dir.path().join("tellme")),
+        )
+        .unwrap();
+        let session = CaptureSession::new(&config);
+
+        session.prepare_new_command("cargo build").unwrap();
+        fs::write(config.output_file(), b"compiling").unwrap();
+        session.finish_command(Some(0)).unwrap();
+        assert_eq!(fs::read(config.output_file()).unwrap(), b"compiling");
+        session.store_last().unwrap();
+
+        let stored = fs::read(config.output_file()).unwrap();
+        assert_eq!(Compression::detect(&stored), Compression::Gzip);
+        assert_eq!(session.read_last().unwrap().1, b"compiling");
+    }
+
+    #[test]
+    fn test_finish_command_keeps_output_that_looks_compressed() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+        let gzip = Compression::Gzip.compress(b"real gzip").unwrap();
+
+        // With compression off, output is kept as printed, whatever its bytes
+        for output in [gzip.as_slice(), b"\x1f\x8bnot really gzip"] {
+            session.prepare_new_command("cat x.gz").unwrap();
+            fs::write(config.output_file(), output).unwrap();
+            let meta = session.finish_command(Some(0)).unwrap().unwrap();
+            assert_eq!(meta.compression, Some(Compression::None));
+            assert_eq!(meta.size, output.len() as u64);
+            assert_eq!(session.read_last().unwrap().1, output);
+        }
+    }
+
+    #[test]
+    fn test_finish_command_records_meta() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+
+        session.prepare_new_command("make test").unwrap();
+        let meta = session.read_meta().unwrap().unwrap();
+        assert_eq!(meta.command, "make test");
+        assert!(!meta.is_complete());
+
+        fs::write(config.output_file(), b"1 failed").unwrap();
+        session.finish_command(Some(2)).unwrap();
+
+        let meta = session.read_meta().unwrap().unwrap();
+        assert!(meta.is_complete());
+        assert_eq!(meta.exit_code, Some(2));
+        assert_eq!(meta.size, 8);
+    }
+
+    #[test]
+    fn test_finish_command_archives() {
+        let (_, temp_dir) = create_test_config_with_tempdir();
+        let config_dir = temp_dir.path().join("my_tellme_config");
+        let archive_dir = temp_dir.path().join("archive");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join(Settings::FILE_NAME),
+            format!(
+                "[archive]\nenabled = true\ndir = {:?}\n",
+                archive_dir.display().to_string()
+            ),
+        )
+        .unwrap();
+        let config = Config::with_paths(
+            Some("99999".to_string()),
+            Some(99999),
+            Some(config_dir),
+            Some(temp_dir.path().join("tellme")),
+        )
+        .unwrap();
+        let session = CaptureSession::new(&config);
+
+        session.prepare_new_command("deploy").unwrap();
+        fs::write(config.output_file(), b"deployed").unwrap();
+        session.finish_command(Some(0)).unwrap();
+        assert!(!archive_dir.exists());
+        session.store_last().unwrap();
+        session.cleanup().unwrap();
+
+        let archive = Archive::new(archive_dir);
+        let entries = archive.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].command, "deploy");
+        assert_eq!(
+            fs::read(archive.output_file(&entries[0].id)).unwrap(),
+            b"deployed"
+        );
+    }
+
+    #[test]
+    fn test_finish_command_keeps_history() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+
+        for cmd in ["make", "ls"] {
+            run_command(&config, cmd, cmd, 0);
+        }
+
+        let history = Archive::new(config.history_dir());
+        let commands: Vec<_> = history
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["make", "ls"]);
+
+        session.cleanup().unwrap();
+        assert!(!config.history_dir().exists());
+    }
+
+    #[test]
+    fn test_store_last() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+        let history = Archive::new(config.history_dir());
+
+        // Output flushed after the command finished is kept too
+        session.prepare_new_command("make").unwrap();
+        fs::write(config.output_file(), b"built").unwrap();
+        session.finish_command(Some(0)).unwrap();
+        fs::write(config.output_file(), b"built\nok").unwrap();
+        session.store_last().unwrap();
+        let meta = session.read_meta().unwrap().unwrap();
+        assert_eq!(meta.size, 8);
+        assert_eq!(session.read_last().unwrap().1, b"built\nok");
+        assert_eq!(
+            fs::read(history.output_file(&meta.id)).unwrap(),
+            b"built\nok"
+        );
+
+        // Storing again does nothing
+        session.store_last().unwrap();
+        assert_eq!(history.entries().unwrap().len(), 1);
+
+        // The next command keeps a capture the background step has not kept yet
+        session.prepare_new_command("ls").unwrap();
+        fs::write(config.output_file(), b"files").unwrap();
+        session.finish_command(Some(0)).unwrap();
+        session.prepare_new_command("pwd").unwrap();
+        let commands: Vec<_> = history
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["make", "ls"]);
+
+        // An unfinished capture is not kept
+        session.store_last().unwrap();
+        assert_eq!(history.entries().unwrap().len(), 2);
+    }
 }
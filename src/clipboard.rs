@@ -0,0 +1,400 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::{Result, TellmeError};
+use crate::secure;
+use crate::settings::ClipboardBackend;
+
+/// Longest OSC 52 payload we send; many terminals drop larger sequences
+const OSC52_MAX_BYTES: usize = 1 << 20;
+
+/// Something that can take copied text
+trait Backend {
+    /// Where the text ends up, for messages (e.g. `clipboard (wl-copy)`)
+    fn describe(&self) -> String;
+
+    /// Copy the text, or explain why it cannot be copied
+    fn copy(&self, text: &str) -> Result<()>;
+}
+
+/// The system clipboard, through the display server
+struct System;
+
+impl Backend for System {
+    fn describe(&self) -> String {
+        "clipboard".to_string()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        let mut clipboard = arboard::Clipboard::new()
+            .map_err(|e| TellmeError::ClipboardUnavailable(e.to_string()))?;
+        clipboard
+            .set_text(text)
+            .map_err(|e| TellmeError::ClipboardUnavailable(format!("failed to copy: {}", e)))?;
+
+        // Give clipboard managers a moment to take ownership before we exit
+        std::thread::sleep(Duration::from_millis(100));
+        Ok(())
+    }
+}
+
+/// OSC 52 escape sequences written to the controlling terminal
+struct Osc52 {
+    /// Wrap the sequence so tmux passes it on to the outer terminal
+    tmux: bool,
+}
+
+impl Backend for Osc52 {
+    fn describe(&self) -> String {
+        "terminal clipboard (OSC 52)".to_string()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        let sequence = osc52_sequence(text, self.tmux)?;
+        // Not stdout, which may be a pipe or carry --json output
+        let mut tty = OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| TellmeError::ClipboardUnavailable(format!("no terminal: {}", e)))?;
+        tty.write_all(sequence.as_bytes())
+            .and_then(|()| tty.flush())
+            .map_err(|e| TellmeError::ClipboardUnavailable(format!("failed to copy: {}", e)))
+    }
+}
+
+/// Build the OSC 52 sequence that sets the clipboard to `text`
+fn osc52_sequence(text: &str, tmux: bool) -> Result<String> {
+    let payload = BASE64.encode(text);
+    if payload.len() > OSC52_MAX_BYTES {
+        return Err(TellmeError::ClipboardUnavailable(format!(
+            "output too large for OSC 52 ({} bytes encoded)",
+            payload.len()
+        )));
+    }
+
+    let osc = format!("\x1b]52;c;{}\x07", payload);
+    Ok(if tmux {
+        // DCS passthrough; escapes inside are doubled
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+    } else {
+        osc
+    })
+}
+
+/// A command reading the text on stdin, such as `wl-copy`
+struct Program {
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+impl Backend for Program {
+    fn describe(&self) -> String {
+        format!("clipboard ({})", self.program)
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        // wl-copy and xclip keep serving the selection in the background, so
+        // their stdout and stderr must not be pipes we would wait on
+        let mut child = Command::new(self.program)
+            .args(self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                TellmeError::ClipboardUnavailable(format!("cannot run {}: {}", self.program, e))
+            })?;
+
+        let written = child
+            .stdin
+            .take()
+            .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+        let status = child.wait()?;
+        if let Err(e) = written {
+            return Err(TellmeError::ClipboardUnavailable(format!(
+                "{} did not read the text: {}",
+                self.program, e
+            )));
+        }
+        if !status.success() {
+            return Err(TellmeError::ClipboardUnavailable(format!(
+                "{} failed ({})",
+                self.program, status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A file only the current user can read
+struct File(PathBuf);
+
+impl Backend for File {
+    fn describe(&self) -> String {
+        self.0.display().to_string()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        if let Some(parent) = self.0.parent() {
+            std::fs::create_dir_all(parent).map_err(TellmeError::io(format!(
+                "Cannot create {}",
+                parent.display()
+            )))?;
+        }
+        secure::write_private(&self.0, text)
+    }
+}
+
+/// Environment seen by auto-detection, replaced in tests
+struct Probe<'a> {
+    /// Whether an environment variable is set and not empty
+    var: &'a dyn Fn(&str) -> bool,
+
+    /// Whether a program is on the `PATH`
+    program: &'a dyn Fn(&str) -> bool,
+
+    /// Whether we run on macOS
+    macos: bool,
+}
+
+/// Backends worth trying in this environment, best first
+fn detect(probe: &Probe) -> Vec<ClipboardBackend> {
+    let mut backends = Vec::new();
+
+    // Over SSH the user's clipboard is on their machine, which only the
+    // terminal can reach; a forwarded X display is slow and often missing
+    let remote = (probe.var)("SSH_TTY") || (probe.var)("SSH_CONNECTION");
+    if remote {
+        backends.push(ClipboardBackend::Osc52);
+    }
+
+    let wayland = (probe.var)("WAYLAND_DISPLAY");
+    let x11 = (probe.var)("DISPLAY");
+    // The commands keep the selection alive after tellme exits
+    if probe.macos && (probe.program)("pbcopy") {
+        backends.push(ClipboardBackend::Pbcopy);
+    }
+    if wayland && (probe.program)("wl-copy") {
+        backends.push(ClipboardBackend::WlCopy);
+    }
+    if x11 && (probe.program)("xclip") {
+        backends.push(ClipboardBackend::Xclip);
+    } else if x11 && (probe.program)("xsel") {
+        backends.push(ClipboardBackend::Xsel);
+    }
+    if probe.macos || wayland || x11 {
+        backends.push(ClipboardBackend::System);
+    }
+
+    if !remote {
+        backends.push(ClipboardBackend::Osc52);
+    }
+    backends.push(ClipboardBackend::File);
+    backends
+}
+
+/// Whether an executable with this name is on the `PATH`
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(program))))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Copies text with the first backend that works
+pub struct Clipboard {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Clipboard {
+    /// Use the backend from the settings, or detect the usable ones
+    pub fn new(config: &Config) -> Self {
+        let kinds = match config.settings().clipboard.backend {
+            ClipboardBackend::Auto => detect(&Probe {
+                var: &|name| env::var_os(name).is_some_and(|v| !v.is_empty()),
+                program: &on_path,
+                macos: cfg!(target_os = "macos"),
+            }),
+            backend => vec![backend],
+        };
+
+        Self {
+            backends: kinds
+                .into_iter()
+                .map(|kind| Self::backend(config, kind))
+                .collect(),
+        }
+    }
+
+    fn backend(config: &Config, kind: ClipboardBackend) -> Box<dyn Backend> {
+        let program = |program, args| Box::new(Program { program, args });
+        match kind {
+            ClipboardBackend::Auto | ClipboardBackend::System => Box::new(System),
+            ClipboardBackend::Osc52 => Box::new(Osc52 {
+                tmux: env::var_os("TMUX").is_some(),
+            }),
+            ClipboardBackend::WlCopy => program("wl-copy", &[]),
+            ClipboardBackend::Xclip => program("xclip", &["-selection", "clipboard"]),
+            ClipboardBackend::Xsel => program("xsel", &["--clipboard", "--input"]),
+            ClipboardBackend::Pbcopy => program("pbcopy", &[]),
+            ClipboardBackend::File => Box::new(File(config.clipboard_file())),
+        }
+    }
+
+    /// Copy text, returning where it went
+    ///
+    /// Fails only when no backend could take it, listing why each one failed.
+    pub fn copy(&self, text: &str) -> Result<String> {
+        let mut reasons = Vec::new();
+        for backend in &self.backends {
+            match backend.copy(text) {
+                Ok(()) => return Ok(backend.describe()),
+                Err(e) => reasons.push(format!("{}: {}", backend.describe(), e)),
+            }
+        }
+        Err(TellmeError::ClipboardUnavailable(reasons.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use tempfile::tempdir;
+
+    /// Records copied text, or fails like an unreachable clipboard
+    struct Fake {
+        name: &'static str,
+        works: bool,
+        copied: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Backend for Fake {
+        fn describe(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn copy(&self, text: &str) -> Result<()> {
+            if !self.works {
+                return Err(TellmeError::ClipboardUnavailable("no display".into()));
+            }
+            self.copied.borrow_mut().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    fn fakes(backends: &[(&'static str, bool)]) -> (Clipboard, Rc<RefCell<Vec<String>>>) {
+        let copied = Rc::new(RefCell::new(Vec::new()));
+        let clipboard = Clipboard {
+            backends: backends
+                .iter()
+                .map(|&(name, works)| {
+                    Box::new(Fake {
+                        name,
+                        works,
+                        copied: copied.clone(),
+                    }) as Box<dyn Backend>
+                })
+                .collect(),
+        };
+        (clipboard, copied)
+    }
+
+    fn detect_with(vars: &[&str], programs: &[&str], macos: bool) -> Vec<ClipboardBackend> {
+        detect(&Probe {
+            var: &|name| vars.contains(&name),
+            program: &|name| programs.contains(&name),
+            macos,
+        })
+    }
+
+    #[test]
+    fn test_falls_back_to_next_backend() {
+        let (clipboard, copied) = fakes(&[("system", false), ("osc52", true), ("file", true)]);
+
+        assert_eq!(clipboard.copy("hello").unwrap(), "osc52");
+        assert_eq!(*copied.borrow(), vec!["hello"]);
+    }
+
+    #[test]
+    fn test_error_lists_every_backend() {
+        let (clipboard, _) = fakes(&[("system", false), ("xclip", false)]);
+
+        let error = clipboard.copy("hello").unwrap_err();
+        assert_eq!(error.exit_code(), 8);
+        let message = error.to_string();
+        assert!(message.contains("system: "), "{}", message);
+        assert!(message.contains("xclip: "), "{}", message);
+    }
+
+    #[test]
+    fn test_detect() {
+        use ClipboardBackend::*;
+
+        assert_eq!(
+            detect_with(&["WAYLAND_DISPLAY"], &["wl-copy"], false),
+            vec![WlCopy, System, Osc52, File]
+        );
+        assert_eq!(
+            detect_with(&["DISPLAY"], &["xsel"], false),
+            vec![Xsel, System, Osc52, File]
+        );
+        assert_eq!(
+            detect_with(&[], &["pbcopy"], true),
+            vec![Pbcopy, System, Osc52, File]
+        );
+        // Over SSH the local terminal comes first, even with X forwarding
+        assert_eq!(
+            detect_with(&["SSH_TTY", "DISPLAY"], &["xclip"], false),
+            vec![Osc52, Xclip, System, File]
+        );
+        // Headless, e.g. in a container
+        assert_eq!(detect_with(&[], &[], false), vec![Osc52, File]);
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("hi", false).unwrap(), "\x1b]52;c;aGk=\x07");
+        assert_eq!(
+            osc52_sequence("hi", true).unwrap(),
+            "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\"
+        );
+        assert!(osc52_sequence(&"x".repeat(OSC52_MAX_BYTES), false).is_err());
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data").join("clipboard.txt");
+
+        File(path.clone()).copy("copied").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "copied");
+        assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_missing_program() {
+        let program = Program {
+            program: "tellme-no-such-program",
+            args: &[],
+        };
+        let error = program.copy("x").unwrap_err().to_string();
+        assert!(
+            error.contains("cannot run tellme-no-such-program"),
+            "{}",
+            error
+        );
+    }
+}
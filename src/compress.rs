@@ -0,0 +1,146 @@
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::error::{Result, TellmeError};
+
+/// Magic bytes at the start of a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic bytes at the start of a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression format for captures and saved logs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Plain, uncompressed data
+    #[default]
+    None,
+
+    /// gzip (`.gz`)
+    Gzip,
+
+    /// zstd (`.zst`)
+    Zstd,
+}
+
+impl Compression {
+    /// Guess the compression from a file extension (`.gz` or `.zst`)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Detect the compression of some data from its magic bytes
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if data.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+
+    /// File extension conventionally used for this format
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Decompress data stored with this format
+    pub fn decompress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let failed = || TellmeError::io("Cannot decompress output");
+        match self {
+            Compression::None => Ok(data),
+            Compression::Gzip => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(data.as_slice())
+                    .read_to_end(&mut decoded)
+                    .map_err(failed())?;
+                Ok(decoded)
+            }
+            Compression::Zstd => zstd::decode_all(data.as_slice()).map_err(failed()),
+        }
+    }
+
+    /// Compress data with this format
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Zstd => Ok(zstd::encode_all(data, 0)?),
+        }
+    }
+}
+
+/// Decompress the stored output of a capture
+///
+/// `compression` comes from the capture's metadata. Captures recorded
+/// before it was tracked have none, and their format is detected from
+/// magic bytes instead.
+pub fn decompress(data: Vec<u8>, compression: Option<Compression>) -> Result<Vec<u8>> {
+    compression
+        .unwrap_or_else(|| Compression::detect(&data))
+        .decompress(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            Compression::from_path(Path::new("a.log.gz")),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("a.log.zst")),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("a.log")),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = b"hello world\nhello world\n".repeat(100);
+
+        for format in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = format.compress(&data).unwrap();
+            assert_eq!(Compression::detect(&compressed), format);
+            assert_eq!(format.decompress(compressed.clone()).unwrap(), data);
+            assert_eq!(decompress(compressed, None).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decompress_plain() {
+        let data = b"\x1b[31mplain\x1b[0m".to_vec();
+        assert_eq!(decompress(data.clone(), None).unwrap(), data);
+
+        // Plain output that looks like gzip is left alone when recorded as plain
+        let fake = b"\x1f\x8bnot really gzip".to_vec();
+        assert_eq!(
+            decompress(fake.clone(), Some(Compression::None)).unwrap(),
+            fake
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, TellmeError};
+use crate::secure;
+use crate::settings::Settings;
 
 pub const DEFAULT_SKIP_COMMANDS: &[&str] = &[
     "tellme", "clear", "exit", "cd", "vim", "vi", "nano", "less", "man", "htop", "top", "ssh",
@@ -18,14 +22,35 @@ pub struct Config {
     /// Directory for temporary log files (default: system temp dir)
     temp_dir: PathBuf,
 
-    /// Shell PID for file naming
+    /// Directory for the archive and other kept data (default: ~/.local/share/tellme)
+    data_dir: PathBuf,
+
+    /// Shell session id for file naming
+    session: String,
+
+    /// Shell PID, for information only
     shell_pid: u32,
+
+    /// Session id and PID of the shell running tellme, kept when attached
+    /// to another shell
+    invoking: (String, u32),
+
+    /// Settings loaded from config.toml
+    settings: Settings,
 }
 
 impl Config {
     /// Create a new Config instance
-    pub fn new() -> anyhow::Result<Self> {
-        Self::with_paths(None, None, None)
+    pub fn new() -> Result<Self> {
+        Self::with_paths(None, None, None, None)
+    }
+
+    /// Create a new Config instance, using the default settings if config.toml is invalid
+    ///
+    /// For the shell integration, which must keep working. Also returns the
+    /// error that was passed over, for the caller to report.
+    pub fn new_lenient() -> Result<(Self, Option<TellmeError>)> {
+        Self::with_paths_lenient(None, None, None, None)
     }
 
     /// Get the configuration directory, respecting TELLME_CONFIG_DIR env var
@@ -45,41 +70,140 @@ impl Config {
         if let Ok(dir) = std::env::var("TELLME_TEMP_DIR") {
             PathBuf::from(dir)
         } else {
-            std::env::temp_dir().join("tellme")
+            secure::default_temp_dir()
+        }
+    }
+
+    /// Get the data directory, respecting TELLME_DATA_DIR env var
+    fn data_dir() -> PathBuf {
+        if let Ok(dir) = std::env::var("TELLME_DATA_DIR") {
+            PathBuf::from(dir)
+        } else {
+            match dirs::data_dir() {
+                Some(dir) => dir.join("tellme"),
+                None => Self::config_dir().join("data"),
+            }
         }
     }
 
     /// Get shell pid, respecting TELLME_SHELL_PID env var
-    fn shell_pid() -> anyhow::Result<u32> {
-        std::env::var("TELLME_SHELL_PID")
-            .unwrap_or_default()
-            .parse()
-            .map_err(|_| anyhow::anyhow!("Invalid TELLME_SHELL_PID value"))
+    ///
+    /// Defaults to the parent process, which is the shell running tellme.
+    fn shell_pid() -> Result<u32> {
+        match std::env::var("TELLME_SHELL_PID") {
+            Ok(pid) => pid
+                .parse()
+                .map_err(|_| TellmeError::InvalidConfig("Invalid TELLME_SHELL_PID value".into())),
+            Err(_) => Ok(std::os::unix::process::parent_id()),
+        }
+    }
+
+    /// Get the shell session id from the TELLME_SESSION env var
+    ///
+    /// Shells started with an older hook have no session; their PID is used.
+    fn env_session(shell_pid: u32) -> Result<String> {
+        match std::env::var("TELLME_SESSION") {
+            Ok(session) if !session.is_empty() => {
+                // The session names files, so it must not contain a path
+                if !Self::is_valid_session(&session) {
+                    return Err(TellmeError::InvalidConfig(
+                        "Invalid TELLME_SESSION value".into(),
+                    ));
+                }
+                Ok(session)
+            }
+            _ => Ok(shell_pid.to_string()),
+        }
+    }
+
+    /// Generate a new session id for a shell
+    pub fn new_session() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    /// Create a Config for these directories and shell, without side effects
+    ///
+    /// Unlike [`Config::new`], no directory is created or checked and no
+    /// `TELLME_*` variable is read, so tools can open the captures of any
+    /// setup. Settings and the recording status are read from `config_dir`
+    /// when it has them.
+    #[allow(dead_code, reason = "library API, unused by the binary")]
+    pub fn from_dirs(
+        config_dir: impl Into<PathBuf>,
+        temp_dir: impl Into<PathBuf>,
+        data_dir: impl Into<PathBuf>,
+        session: &str,
+        shell_pid: u32,
+    ) -> Result<Self> {
+        if !Self::is_valid_session(session) {
+            return Err(TellmeError::InvalidInput(format!(
+                "Invalid session id '{}'",
+                session
+            )));
+        }
+        let config_dir = config_dir.into();
+
+        Ok(Self {
+            recording_enabled: fs::read_to_string(config_dir.join("status"))
+                .is_ok_and(|content| content.trim() == "enabled"),
+            settings: Settings::load(&config_dir)?,
+            config_dir,
+            temp_dir: temp_dir.into(),
+            data_dir: data_dir.into(),
+            invoking: (session.to_string(), shell_pid),
+            session: session.to_string(),
+            shell_pid,
+        })
     }
 
     /// Create a new Config instance with explicit paths
     pub(crate) fn with_paths(
+        session: Option<String>,
+        shell_pid: Option<u32>,
+        config_dir: Option<PathBuf>,
+        temp_dir: Option<PathBuf>,
+    ) -> Result<Self> {
+        match Self::with_paths_lenient(session, shell_pid, config_dir, temp_dir)? {
+            (_, Some(e)) => Err(e),
+            (config, None) => Ok(config),
+        }
+    }
+
+    /// Like [`Config::with_paths`], but invalid settings give the defaults and the error
+    fn with_paths_lenient(
+        session: Option<String>,
         shell_pid: Option<u32>,
         config_dir: Option<PathBuf>,
         temp_dir: Option<PathBuf>,
-    ) -> anyhow::Result<Self> {
-        let shell_pid = shell_pid.map_or_else(|| Self::shell_pid(), |p| Ok(p))?;
+    ) -> Result<(Self, Option<TellmeError>)> {
+        let shell_pid = shell_pid.map_or_else(Self::shell_pid, Ok)?;
+        let session = session.map_or_else(|| Self::env_session(shell_pid), Ok)?;
         let config_dir = config_dir.unwrap_or_else(Self::config_dir);
         let temp_dir = temp_dir.unwrap_or_else(Self::temp_dir);
 
         fs::create_dir_all(&config_dir)?;
-        fs::create_dir_all(&temp_dir)?;
-
-        Ok(Self {
+        // Captured output may hold secrets, so keep it away from other users
+        secure::private_dir(&temp_dir)?;
+
+        let (settings, invalid) = match Settings::load(&config_dir) {
+            Ok(settings) => (settings, None),
+            Err(e) => (Settings::default(), Some(e)),
+        };
+        let config = Self {
             recording_enabled: Self::load_recording_status(&config_dir),
+            settings,
             config_dir,
             temp_dir,
+            data_dir: Self::data_dir(),
+            invoking: (session.clone(), shell_pid),
+            session,
             shell_pid,
-        })
+        };
+        Ok((config, invalid))
     }
 
     /// Load recording status from status file in specific directory
-    fn load_recording_status(config_dir: &PathBuf) -> bool {
+    fn load_recording_status(config_dir: &Path) -> bool {
         let status_file = config_dir.join("status");
 
         match fs::read_to_string(&status_file) {
@@ -101,21 +225,180 @@ impl Config {
         self.config_dir.join("skip_commands")
     }
 
+    /// Kinds of temp files a shell session keeps, named `.tellme_<kind>_<session>`
+    ///
+    /// The shell marker comes last, so it is removed after everything else.
+    const TEMP_FILE_KINDS: [&str; 6] = ["cmd", "output", "meta", "done", "lock", "shell"];
+
+    /// Check that a session id is safe to use in a file name
+    pub(crate) fn is_valid_session(session: &str) -> bool {
+        !session.is_empty()
+            && session
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// The session a temp file name belongs to, if it is one of ours
+    pub fn session_of_temp_file(name: &str) -> Option<&str> {
+        Self::TEMP_FILE_KINDS
+            .iter()
+            .find_map(|kind| name.strip_prefix(&format!(".tellme_{}_", kind)))
+            // Skips files being written, such as `.tellme_meta_<session>.<pid>-<id>.tmp`
+            .filter(|session| Self::is_valid_session(session))
+            // A curl config left behind is enough to find a dead shell's files
+            .or_else(|| {
+                name.strip_prefix(".tellme_share_")
+                    .and_then(|rest| rest.split_once('.'))
+                    .map(|(session, _)| session)
+                    .filter(|session| Self::is_valid_session(session))
+            })
+    }
+
+    /// Get the path of a temp file of the shell session with this id
+    pub(crate) fn shell_temp_file(&self, kind: &str, session: &str) -> PathBuf {
+        self.temp_dir.join(format!(".tellme_{}_{}", kind, session))
+    }
+
     /// Get the command file path
     pub fn cmd_file(&self) -> PathBuf {
-        self.temp_dir
-            .join(format!(".tellme_cmd_{}", self.shell_pid))
+        self.shell_temp_file("cmd", &self.session)
     }
 
     /// Get the log file path
     pub fn output_file(&self) -> PathBuf {
-        self.temp_dir
-            .join(format!(".tellme_output_{}", self.shell_pid))
+        self.shell_temp_file("output", &self.session)
+    }
+
+    /// Get the capture metadata file path
+    pub fn meta_file(&self) -> PathBuf {
+        self.shell_temp_file("meta", &self.session)
+    }
+
+    /// Get the file marking that the current capture was written completely
+    pub fn done_file(&self) -> PathBuf {
+        self.shell_temp_file("done", &self.session)
+    }
+
+    /// Get the file locked while the current capture is written or read
+    pub fn lock_file(&self) -> PathBuf {
+        self.shell_temp_file("lock", &self.session)
+    }
+
+    /// Get a new path for the curl config of an upload, which may hold tokens
+    ///
+    /// Unique to the upload, so shares running at once from this shell do
+    /// not overwrite each other's credentials.
+    pub fn share_file(&self) -> PathBuf {
+        self.temp_dir.join(format!(
+            "{}{}-{}",
+            Self::share_file_prefix(&self.session),
+            std::process::id(),
+            uuid::Uuid::new_v4().simple()
+        ))
+    }
+
+    /// Start of the names of the curl configs of a session's uploads
+    fn share_file_prefix(session: &str) -> String {
+        format!(".tellme_share_{}.", session)
     }
 
-    /// Get all temp files for this shell
-    pub fn temp_files(&self) -> Vec<PathBuf> {
-        vec![self.cmd_file(), self.output_file()]
+    /// Get the file recording when this shell started
+    pub fn shell_file(&self) -> PathBuf {
+        self.shell_temp_file("shell", &self.session)
+    }
+
+    /// Get all temp files of the shell session with this id
+    ///
+    /// Includes the curl configs of uploads in progress, or left behind by a
+    /// tellme that was killed; the shell marker stays last.
+    pub fn temp_files_of(&self, session: &str) -> Vec<PathBuf> {
+        let mut files: Vec<_> = Self::TEMP_FILE_KINDS
+            .iter()
+            .map(|kind| self.shell_temp_file(kind, session))
+            .collect();
+        let marker = files.pop();
+
+        let prefix = Self::share_file_prefix(session);
+        if let Ok(entries) = fs::read_dir(&self.temp_dir) {
+            files.extend(
+                entries
+                    .flatten()
+                    .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+                    .map(|e| e.path()),
+            );
+        }
+        files.extend(marker);
+        files
+    }
+
+    /// Get the temporary directory holding every shell's captures
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_dir
+    }
+
+    /// Get the directory holding the completed captures of every shell
+    pub fn history_root(&self) -> PathBuf {
+        self.temp_dir.join("history")
+    }
+
+    /// Get the directory holding this shell's completed captures
+    pub fn history_dir(&self) -> PathBuf {
+        self.history_dir_of(&self.session)
+    }
+
+    /// Get the directory holding the completed captures of the shell session with this id
+    pub fn history_dir_of(&self, session: &str) -> PathBuf {
+        self.history_root().join(session)
+    }
+
+    /// Get the archive directory, from settings or under the data directory
+    pub fn archive_dir(&self) -> PathBuf {
+        self.settings
+            .archive
+            .dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("archive"))
+    }
+
+    /// Get the file the `file` clipboard backend writes, from settings or under the data directory
+    pub fn clipboard_file(&self) -> PathBuf {
+        self.settings
+            .clipboard
+            .file
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("clipboard.txt"))
+    }
+
+    /// Get the shell session this config is bound to
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Get the PID of the shell this config is bound to
+    pub fn pid(&self) -> u32 {
+        self.shell_pid
+    }
+
+    /// Act on behalf of another shell session, e.g. to read its captures
+    pub fn attach(&mut self, session: &str, shell_pid: u32) {
+        self.session = session.to_string();
+        self.shell_pid = shell_pid;
+    }
+
+    /// A copy of this config bound to the shell running tellme, even when
+    /// attached to another, for recording new captures
+    pub fn invoking_shell(&self) -> Config {
+        let (session, shell_pid) = self.invoking.clone();
+        Config {
+            session,
+            shell_pid,
+            ..self.clone()
+        }
+    }
+
+    /// Get the settings loaded from config.toml
+    pub fn settings(&self) -> &Settings {
+        &self.settings
     }
 
     /// Check if recording is enabled
@@ -124,9 +407,9 @@ impl Config {
     }
 
     /// Set recording status
-    pub fn set_recording_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+    pub fn set_recording_enabled(&mut self, enabled: bool) -> Result<()> {
         fs::write(
-            &self.status_file(),
+            self.status_file(),
             if enabled { "enabled" } else { "disabled" },
         )?;
 
@@ -153,10 +436,10 @@ impl Config {
     }
 
     /// Save skip commands to file
-    pub fn save_skip_commands(&self, commands: &[String]) -> anyhow::Result<()> {
+    pub fn save_skip_commands(&self, commands: &[String]) -> Result<()> {
         fs::create_dir_all(&self.config_dir)?;
         let content = commands.join("\n");
-        fs::write(&self.skip_commands_file(), content)?;
+        fs::write(self.skip_commands_file(), content)?;
         Ok(())
     }
 }
@@ -205,6 +488,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_data_dir_env_var() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("tellme_data");
+
+        unsafe {
+            env::set_var("TELLME_DATA_DIR", &path);
+        }
+
+        assert_eq!(Config::data_dir(), path);
+
+        unsafe {
+            env::remove_var("TELLME_DATA_DIR");
+        }
+    }
+
     #[test]
     fn test_shell_pid_env_var() {
         let _lock = ENV_LOCK.lock().unwrap();
@@ -213,23 +513,126 @@ mod tests {
             env::set_var("TELLME_SHELL_PID", "12345");
         }
 
-        assert_eq!(Config::shell_pid().unwrap(), 12345)
+        assert_eq!(Config::shell_pid().unwrap(), 12345);
+
+        unsafe {
+            env::remove_var("TELLME_SHELL_PID");
+        }
+        assert_eq!(
+            Config::shell_pid().unwrap(),
+            std::os::unix::process::parent_id()
+        );
+    }
+
+    #[test]
+    fn test_session_env_var() {
+        let _lock = ENV_LOCK.lock().unwrap();
+
+        unsafe {
+            env::set_var("TELLME_SESSION", "0b6e61a4-5d0e-4f4b-9d43-1f0f5a1c2b3d");
+        }
+        assert_eq!(
+            Config::env_session(42).unwrap(),
+            "0b6e61a4-5d0e-4f4b-9d43-1f0f5a1c2b3d"
+        );
+
+        unsafe {
+            env::set_var("TELLME_SESSION", "../../etc");
+        }
+        assert!(Config::env_session(42).is_err());
+
+        // Shells started by an older hook are named by their PID
+        unsafe {
+            env::remove_var("TELLME_SESSION");
+        }
+        assert_eq!(Config::env_session(42).unwrap(), "42");
+
+        assert_ne!(Config::new_session(), Config::new_session());
     }
 
     #[test]
     fn test_file_paths() {
         let tmp_dir = tempdir().unwrap().path().join("tellme");
 
-        let config = Config::with_paths(Some(99999), None, Some(tmp_dir.clone())).unwrap();
-        assert_eq!(config.cmd_file(), tmp_dir.join(".tellme_cmd_99999"));
-        assert_eq!(config.output_file(), tmp_dir.join(".tellme_output_99999"));
+        let config = Config::with_paths(
+            Some("abc".to_string()),
+            Some(99999),
+            None,
+            Some(tmp_dir.clone()),
+        )
+        .unwrap();
+        assert_eq!(config.pid(), 99999);
+        assert_eq!(config.cmd_file(), tmp_dir.join(".tellme_cmd_abc"));
+        assert_eq!(config.output_file(), tmp_dir.join(".tellme_output_abc"));
+        assert_eq!(config.meta_file(), tmp_dir.join(".tellme_meta_abc"));
+        assert_eq!(config.shell_file(), tmp_dir.join(".tellme_shell_abc"));
+        assert_eq!(config.history_dir(), tmp_dir.join("history").join("abc"));
+        assert_eq!(
+            config.temp_files_of("xyz")[0],
+            tmp_dir.join(".tellme_cmd_xyz")
+        );
+
+        // Every upload gets its own curl config, found with the session's files
+        let share = config.share_file();
+        assert_ne!(share, config.share_file());
+        assert!(
+            share
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".tellme_share_abc.")
+        );
+        fs::write(&share, "").unwrap();
+        let files = config.temp_files_of("abc");
+        assert!(files.contains(&share));
+        assert_eq!(files.last(), Some(&config.shell_file()));
+        assert!(!config.temp_files_of("ab").contains(&share));
+
+        assert_eq!(
+            Config::session_of_temp_file(".tellme_done_abc"),
+            Some("abc")
+        );
+        assert_eq!(Config::session_of_temp_file(".tellme_meta_abc.tmp"), None);
+        assert_eq!(
+            Config::session_of_temp_file(".tellme_share_abc.42-ff"),
+            Some("abc")
+        );
+        assert_eq!(Config::session_of_temp_file("index.json"), None);
+    }
+
+    #[test]
+    fn test_from_dirs() {
+        let root = tempdir().unwrap();
+        let dir = |name: &str| root.path().join(name);
+
+        let config = Config::from_dirs(dir("config"), dir("tmp"), dir("data"), "abc", 42).unwrap();
+        assert_eq!(config.output_file(), dir("tmp").join(".tellme_output_abc"));
+        assert_eq!(config.archive_dir(), dir("data").join("archive"));
+        assert_eq!(config.pid(), 42);
+        assert!(!config.is_recording_enabled());
+        // Nothing was created
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+
+        fs::create_dir(dir("config")).unwrap();
+        fs::write(dir("config").join("status"), "enabled").unwrap();
+        let config = Config::from_dirs(dir("config"), dir("tmp"), dir("data"), "abc", 42).unwrap();
+        assert!(config.is_recording_enabled());
+
+        let error = Config::from_dirs(dir("config"), dir("tmp"), dir("data"), "../x", 42);
+        assert_eq!(error.unwrap_err().exit_code(), 2);
     }
 
     #[test]
     fn test_skip_commands_default() {
         let temp_config = tempdir().unwrap().path().join("tellme_config");
 
-        let config = Config::with_paths(Some(99999), Some(temp_config), None).unwrap();
+        let config = Config::with_paths(
+            Some("99999".to_string()),
+            Some(99999),
+            Some(temp_config),
+            None,
+        )
+        .unwrap();
         let skip_commands = config.skip_commands();
 
         assert!(skip_commands.contains(&"vim".to_string()));
@@ -246,7 +649,13 @@ mod tests {
         let skip_file = temp_config.join("skip_commands");
         fs::write(&skip_file, "custom_cmd\nanother_cmd\n").unwrap();
 
-        let config = Config::with_paths(Some(99999), Some(temp_config), None).unwrap();
+        let config = Config::with_paths(
+            Some("99999".to_string()),
+            Some(99999),
+            Some(temp_config),
+            None,
+        )
+        .unwrap();
         let skip_commands = config.skip_commands();
 
         assert!(skip_commands.contains(&"custom_cmd".to_string()));
@@ -259,7 +668,13 @@ mod tests {
     fn test_set_recording_enabled() {
         let temp_config = tempdir().unwrap().path().join("tellme_config");
 
-        let mut config = Config::with_paths(Some(99999), Some(temp_config), None).unwrap();
+        let mut config = Config::with_paths(
+            Some("99999".to_string()),
+            Some(99999),
+            Some(temp_config),
+            None,
+        )
+        .unwrap();
 
         // Initially should be disabled (or whatever status file says)
         let initial_status = config.is_recording_enabled();
@@ -282,23 +697,50 @@ pub(crate) mod test_utils {
     pub fn create_test_config() -> Config {
         let temp_dir = tempdir().unwrap();
 
-        Config::with_paths(
+        let mut config = Config::with_paths(
+            Some("99999".to_string()),
             Some(99999),
             Some(temp_dir.path().join("my_tellme_config")),
             Some(temp_dir.path().join("tellme")),
         )
-        .unwrap()
+        .unwrap();
+        // Never touch the real archive from tests
+        config.settings.archive.dir = Some(temp_dir.path().join("archive"));
+        config
+    }
+
+    /// Simulate the shell hooks capturing a command that printed `output`
+    ///
+    /// Includes the background step that keeps the capture.
+    pub fn run_command(config: &Config, cmd: &str, output: &str, exit_code: i32) {
+        let session = crate::capture::CaptureSession::new(config);
+        session.prepare_new_command(cmd).unwrap();
+        fs::write(config.output_file(), output).unwrap();
+        session.finish_command(Some(exit_code)).unwrap();
+        session.store_last().unwrap();
+    }
+
+    /// A copy of `config` for another shell sharing the same directories
+    pub fn create_test_config_for_shell(config: &Config, pid: u32) -> Config {
+        Config {
+            session: pid.to_string(),
+            shell_pid: pid,
+            invoking: (pid.to_string(), pid),
+            ..config.clone()
+        }
     }
 
     pub fn create_test_config_with_tempdir() -> (Config, TempDir) {
         let temp_dir = tempdir().unwrap();
 
-        let config = Config::with_paths(
+        let mut config = Config::with_paths(
+            Some("99999".to_string()),
             Some(99999),
             Some(temp_dir.path().join("my_tellme_config")),
             Some(temp_dir.path().join("tellme")),
         )
         .unwrap();
+        config.settings.archive.dir = Some(temp_dir.path().join("archive"));
 
         (config, temp_dir)
     }
@@ -0,0 +1,257 @@
+use regex::Regex;
+use serde::Serialize;
+use similar::udiff::UnifiedHunkHeader;
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::sync::LazyLock;
+
+use crate::humanize;
+
+/// Line counts of the differences between two outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSummary {
+    /// Lines only in the new output
+    pub added: usize,
+
+    /// Lines only in the old output
+    pub removed: usize,
+}
+
+impl DiffSummary {
+    /// Whether both outputs are identical
+    pub fn is_unchanged(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+}
+
+/// Count the lines added and removed between two outputs
+pub fn summarize(old: &str, new: &str) -> DiffSummary {
+    let diff = TextDiff::from_lines(old, new);
+    let mut summary = DiffSummary::default();
+
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => summary.added += 1,
+            ChangeTag::Delete => summary.removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    summary
+}
+
+/// Kind of a line in a rendered diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    /// File header (`---` / `+++`)
+    Header,
+
+    /// Hunk header (`@@ -1,3 +1,4 @@`)
+    Hunk,
+
+    /// Line present in both outputs
+    Equal,
+
+    /// Line only in the old output
+    Delete,
+
+    /// Line only in the new output
+    Insert,
+
+    /// Side by side: an old line replaced by a new one
+    Change,
+}
+
+/// A line of a rendered diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    /// What the line shows
+    pub kind: LineKind,
+
+    /// The rendered text, without the trailing newline
+    pub text: String,
+}
+
+impl DiffLine {
+    fn new(kind: LineKind, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+        }
+    }
+}
+
+/// Render a unified diff with `context` lines around each change
+pub fn unified(old: &str, new: &str, names: (&str, &str), context: usize) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = vec![
+        DiffLine::new(LineKind::Header, format!("--- {}", names.0)),
+        DiffLine::new(LineKind::Header, format!("+++ {}", names.1)),
+    ];
+
+    for hunk in diff.unified_diff().context_radius(context).iter_hunks() {
+        lines.push(DiffLine::new(LineKind::Hunk, hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let (kind, sign) = match change.tag() {
+                ChangeTag::Equal => (LineKind::Equal, ' '),
+                ChangeTag::Delete => (LineKind::Delete, '-'),
+                ChangeTag::Insert => (LineKind::Insert, '+'),
+            };
+            let text = change.value().trim_end_matches(['\n', '\r']);
+            lines.push(DiffLine::new(kind, format!("{}{}", sign, text)));
+        }
+    }
+
+    lines
+}
+
+/// Render a side-by-side diff fitting in `width` columns
+///
+/// Like `sdiff`, the gutter marks changed lines with `|`, removed lines
+/// with `<` and added lines with `>`.
+pub fn side_by_side(old: &str, new: &str, context: usize, width: usize) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let column = width.saturating_sub(3) / 2;
+    let fit = |line: Option<&&str>| {
+        let line = line.map(|l| l.replace('\t', "    ")).unwrap_or_default();
+        let line = humanize::truncate(&line, column);
+        format!("{:<column$}", line)
+    };
+
+    let mut lines = Vec::new();
+    for group in diff.grouped_ops(context) {
+        lines.push(DiffLine::new(
+            LineKind::Hunk,
+            UnifiedHunkHeader::new(&group).to_string(),
+        ));
+
+        for op in &group {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            for i in 0..old_range.len().max(new_range.len()) {
+                let left = (i < old_range.len()).then(|| &old_lines[old_range.start + i]);
+                let right = (i < new_range.len()).then(|| &new_lines[new_range.start + i]);
+                let (kind, marker) = match (tag, left, right) {
+                    (DiffTag::Equal, _, _) => (LineKind::Equal, ' '),
+                    (_, Some(_), Some(_)) => (LineKind::Change, '|'),
+                    (_, Some(_), None) => (LineKind::Delete, '<'),
+                    _ => (LineKind::Insert, '>'),
+                };
+                let text = format!("{} {} {}", fit(left), marker, fit(right));
+                lines.push(DiffLine::new(kind, text.trim_end()));
+            }
+        }
+    }
+
+    lines
+}
+
+/// Volatile tokens replaced by [`normalize`], in the order they are applied
+static VOLATILE: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    let temp_dir = std::env::temp_dir();
+    let temp_dir = regex::escape(temp_dir.to_string_lossy().trim_end_matches('/'));
+    let patterns = [
+        (
+            format!(
+                r#"(?:{}|/tmp|/var/tmp|/private/var/folders|/var/folders)/[^\s:'"),]*"#,
+                temp_dir
+            ),
+            "<tmp>",
+        ),
+        (
+            r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?"
+                .to_string(),
+            "<timestamp>",
+        ),
+        (r"\b\d{1,2}:\d{2}:\d{2}(?:[.,]\d+)?\b".to_string(), "<time>"),
+        (r"\b0x[0-9a-fA-F]+\b".to_string(), "<hex>"),
+        (
+            r"\b(?:\d+h)?(?:\d+m)?\d+(?:\.\d+)?\s?(?:ns|µs|us|ms|s|secs?|seconds?|min|minutes?)\b"
+                .to_string(),
+            "<duration>",
+        ),
+    ];
+
+    patterns
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(&pattern).unwrap(), replacement))
+        .collect()
+});
+
+/// Replace tokens that change between runs (timestamps, durations, hex
+/// addresses and temp paths) with placeholders
+pub fn normalize(text: &str) -> String {
+    VOLATILE
+        .iter()
+        .fold(text.to_string(), |text, (regex, replacement)| {
+            regex.replace_all(&text, *replacement).into_owned()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let old = "running 3 tests\ntest a ... ok\ntest b ... FAILED\n";
+        let new = "running 3 tests\ntest a ... ok\ntest b ... ok\ntest c ... ok\n";
+
+        let summary = summarize(old, new);
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 2,
+                removed: 1
+            }
+        );
+        assert!(!summary.is_unchanged());
+        assert!(summarize(old, old).is_unchanged());
+    }
+
+    #[test]
+    fn test_unified() {
+        let lines = unified("a\nb\nc\n", "a\nB\nc\n", ("old", "new"), 1);
+        let texts: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "--- old",
+                "+++ new",
+                "@@ -1,3 +1,3 @@",
+                " a",
+                "-b",
+                "+B",
+                " c"
+            ]
+        );
+        assert_eq!(lines[4].kind, LineKind::Delete);
+        assert_eq!(lines[5].kind, LineKind::Insert);
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let lines = side_by_side("a\nb\nc\n", "a\nB\nc\nd\n", 0, 13);
+        let texts: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["@@ -2 +2 @@", "b     | B", "@@ -3,0 +4 @@", "      > d"]
+        );
+        assert_eq!(lines[1].kind, LineKind::Change);
+        assert_eq!(lines[3].kind, LineKind::Insert);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("2024-05-01T12:00:03Z started at 0x7ffd1a2b in /tmp/.tmpAb12/out.txt"),
+            "<timestamp> started at <hex> in <tmp>"
+        );
+        assert_eq!(
+            normalize("test result: ok. finished in 0.46s (12ms, 3m04s) at 14:03:59"),
+            "test result: ok. finished in <duration> (<duration>, <duration>) at <time>"
+        );
+        assert_eq!(normalize("3 tests passed"), "3 tests passed");
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+/// Errors returned by tellme
+///
+/// Each kind of error has its own process exit code, see [`TellmeError::exit_code`].
+#[derive(Debug, thiserror::Error)]
+pub enum TellmeError {
+    /// This shell has no capture to act on
+    #[error(
+        "No previous command record found. Maybe recording was disabled? \
+         Run 'tellme status' to check, or last command was skipped"
+    )]
+    NoPreviousCapture,
+
+    /// A capture, shell or directory that was asked for does not exist
+    #[error("{0}")]
+    NotFound(String),
+
+    /// Recording is turned off, so nothing was captured
+    #[error("Recording is disabled. Run 'tellme on' to start capturing")]
+    RecordingDisabled,
+
+    /// The last capture is still being written, or its files do not match
+    #[error("{0}")]
+    IncompleteCapture(String),
+
+    /// An argument has an invalid value, such as a pattern or duration
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// A prefix matches more than one capture or shell
+    #[error("{kind} '{key}' is ambiguous")]
+    Ambiguous {
+        /// What was looked up, e.g. "Capture id"
+        kind: &'static str,
+        /// The prefix that was given
+        key: String,
+    },
+
+    /// The config file or a `TELLME_*` environment variable is invalid
+    #[error("{0}")]
+    InvalidConfig(String),
+
+    /// A file could not be read or written
+    #[error("{context}: {source}")]
+    Io {
+        /// What was being done, e.g. "Cannot read output of capture 1a2b3c4d"
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A path is not safe to use, e.g. a symlink or owned by another user
+    #[error("{0}")]
+    UnsafePath(String),
+
+    /// A stored file could not be parsed
+    #[error("Corrupt {}: {message}", path.display())]
+    Corrupt {
+        /// The unreadable file
+        path: PathBuf,
+        /// What is wrong with it
+        message: String,
+    },
+
+    /// Data could not be converted to or from JSON
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The clipboard cannot be used, e.g. without a display server
+    #[error("Clipboard unavailable: {0}")]
+    ClipboardUnavailable(String),
+
+    /// A capture could not be uploaded to the paste service
+    #[error("Upload failed: {0}")]
+    UploadFailed(String),
+
+    /// A desktop notification could not be shown
+    #[error("Notification failed: {0}")]
+    NotificationFailed(String),
+}
+
+impl TellmeError {
+    /// Wrap an I/O error with what was being done
+    pub(crate) fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |source| Self::Io { context, source }
+    }
+
+    /// Process exit code for this error
+    ///
+    /// | Code | Meaning                                        |
+    /// |------|------------------------------------------------|
+    /// | 1    | Any other error                                |
+    /// | 2    | Invalid arguments                              |
+    /// | 3    | No capture (or shell) found                    |
+    /// | 4    | Recording is disabled                          |
+    /// | 5    | The capture is still running or being rewritten |
+    /// | 6    | Invalid configuration                          |
+    /// | 7    | File could not be read or written              |
+    /// | 8    | Clipboard unavailable                          |
+    /// | 9    | Upload to the paste service failed             |
+    /// | 10   | Desktop notification failed                    |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::InvalidInput(_) | Self::Ambiguous { .. } => 2,
+            Self::NoPreviousCapture | Self::NotFound(_) => 3,
+            Self::RecordingDisabled => 4,
+            Self::IncompleteCapture(_) => 5,
+            Self::InvalidConfig(_) => 6,
+            Self::Io { .. } | Self::UnsafePath(_) | Self::Corrupt { .. } | Self::Json(_) => 7,
+            Self::ClipboardUnavailable(_) => 8,
+            Self::UploadFailed(_) => 9,
+            Self::NotificationFailed(_) => 10,
+        }
+    }
+}
+
+impl From<std::io::Error> for TellmeError {
+    fn from(source: std::io::Error) -> Self {
+        Self::Io {
+            context: "I/O error".to_string(),
+            source,
+        }
+    }
+}
+
+/// Result type of tellme
+pub type Result<T, E = TellmeError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct_per_kind() {
+        assert_eq!(TellmeError::NoPreviousCapture.exit_code(), 3);
+        assert_eq!(TellmeError::RecordingDisabled.exit_code(), 4);
+        assert_eq!(TellmeError::InvalidConfig("bad".into()).exit_code(), 6);
+        assert_eq!(
+            TellmeError::from(std::io::Error::other("disk full")).exit_code(),
+            7
+        );
+        assert_eq!(
+            TellmeError::ClipboardUnavailable("no display".into()).exit_code(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_io_context() {
+        let error = TellmeError::io("Cannot read x")(std::io::Error::other("denied"));
+        assert_eq!(error.to_string(), "Cannot read x: denied");
+    }
+}
@@ -0,0 +1,475 @@
+use chrono::Local;
+use clap::ValueEnum;
+use clap::builder::PossibleValue;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::compress::Compression;
+use crate::error::Result;
+use crate::summary::{self, Diagnostic, Severity};
+
+/// Format of a saved log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Plain text with a command header
+    #[default]
+    Text,
+
+    /// A JSON object with the command, summary and output
+    Json,
+
+    /// `file:line:col: message` lines for vim's `:cfile`
+    Quickfix,
+
+    /// A JSON list of problems, as VS Code's Problems panel exports them
+    Vscode,
+}
+
+impl LogFormat {
+    /// Infer the format from a file name, ignoring a compression extension
+    pub fn from_path(path: &Path) -> Self {
+        let path = match Compression::from_path(path) {
+            Compression::None => path.to_path_buf(),
+            _ => path.with_extension(""),
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => LogFormat::Json,
+            Some("err") => LogFormat::Quickfix,
+            _ => LogFormat::Text,
+        }
+    }
+
+    /// Whether the format lists diagnostics rather than the output
+    pub fn lists_diagnostics(self) -> bool {
+        matches!(self, LogFormat::Quickfix | LogFormat::Vscode)
+    }
+
+    /// File extension for this format
+    fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Text => "log",
+            LogFormat::Json | LogFormat::Vscode => "json",
+            LogFormat::Quickfix => "err",
+        }
+    }
+}
+
+/// Value of `--format`: a format, or `auto` to pick one from the file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatArg {
+    /// This format, whatever the output name
+    Format(LogFormat),
+
+    /// json for a .json output name, quickfix for .err, text otherwise
+    Auto,
+}
+
+impl Default for FormatArg {
+    fn default() -> Self {
+        FormatArg::Format(LogFormat::default())
+    }
+}
+
+impl FormatArg {
+    /// The format to save in; `auto` looks at the output name, if any
+    pub fn resolve(self, output: Option<&Path>) -> LogFormat {
+        match self {
+            FormatArg::Format(format) => format,
+            FormatArg::Auto => output.map_or(LogFormat::Text, LogFormat::from_path),
+        }
+    }
+}
+
+impl ValueEnum for FormatArg {
+    fn value_variants<'a>() -> &'a [Self] {
+        static VARIANTS: LazyLock<Vec<FormatArg>> = LazyLock::new(|| {
+            LogFormat::value_variants()
+                .iter()
+                .copied()
+                .map(FormatArg::Format)
+                .chain([FormatArg::Auto])
+                .collect()
+        });
+        &VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            FormatArg::Format(format) => format.to_possible_value(),
+            FormatArg::Auto => Some(
+                PossibleValue::new("auto")
+                    .help("json for a .json output name, quickfix for .err, text otherwise"),
+            ),
+        }
+    }
+}
+
+/// A capture to write out
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Log<'a> {
+    /// The command line
+    pub command: &'a str,
+
+    /// Diagnostics extracted from the output, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<&'a [Diagnostic]>,
+
+    /// The output of the command, left out with `--summary-only`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<&'a str>,
+
+    /// Directory the command ran in, which relative paths in the summary
+    /// are resolved against for editors
+    #[serde(skip)]
+    pub cwd: Option<&'a Path>,
+}
+
+impl<'a> Log<'a> {
+    /// Create a log without a summary
+    pub fn new(command: &'a str, output: &'a str) -> Self {
+        Self {
+            command,
+            summary: None,
+            output: Some(output),
+            cwd: None,
+        }
+    }
+
+    /// Set the directory the command ran in
+    pub fn with_cwd(self, cwd: &'a Path) -> Self {
+        Self {
+            cwd: Some(cwd),
+            ..self
+        }
+    }
+
+    /// Add a summary of diagnostics
+    pub fn with_summary(self, summary: &'a [Diagnostic]) -> Self {
+        Self {
+            summary: Some(summary),
+            ..self
+        }
+    }
+
+    /// Keep the summary but leave out the output
+    pub fn without_output(self) -> Self {
+        Self {
+            output: None,
+            ..self
+        }
+    }
+}
+
+/// Write a capture with its command header, and its summary if any
+pub fn write_log(out: &mut impl Write, log: &Log) -> std::io::Result<()> {
+    writeln!(out, "Command:\n{}", log.command)?;
+    writeln!(out, "=============================\n")?;
+
+    if let Some(diagnostics) = log.summary {
+        writeln!(out, "Summary: {}", summary::describe(diagnostics))?;
+        writeln!(out, "-----------------------------")?;
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}", diagnostic.text)?;
+        }
+        if log.output.is_none() {
+            return Ok(());
+        }
+        writeln!(out, "=============================\n")?;
+    }
+
+    match log.output {
+        Some(output) => writeln!(out, "{}", output),
+        None => Ok(()),
+    }
+}
+
+/// Path of a diagnostic's file, joined to the directory the command ran in
+/// when relative, so editors opened elsewhere still find it
+fn resolve_file(file: &str, cwd: Option<&Path>) -> String {
+    match cwd {
+        Some(cwd) if Path::new(file).is_relative() => cwd.join(file).display().to_string(),
+        _ => file.to_string(),
+    }
+}
+
+/// Write diagnostics as `file:line:col: severity: message` lines
+///
+/// Diagnostics without a file are left out, as an editor cannot jump to them.
+/// Relative files are resolved against `cwd` when given.
+pub fn write_quickfix(
+    out: &mut impl Write,
+    diagnostics: &[Diagnostic],
+    cwd: Option<&Path>,
+) -> std::io::Result<()> {
+    for diagnostic in diagnostics {
+        let Some(file) = &diagnostic.file else {
+            continue;
+        };
+        let file = resolve_file(file, cwd);
+        let severity = diagnostic.severity.as_str();
+        let message = if diagnostic.message.starts_with(severity) {
+            diagnostic.message.clone()
+        } else {
+            format!("{}: {}", severity, diagnostic.message)
+        };
+        writeln!(
+            out,
+            "{}:{}:{}: {}",
+            file,
+            diagnostic.line.unwrap_or(1),
+            diagnostic.column.unwrap_or(1),
+            message
+        )?;
+    }
+    Ok(())
+}
+
+/// A problem in the format of VS Code's Problems panel
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VscodeProblem<'a> {
+    resource: String,
+    owner: &'static str,
+    source: &'a str,
+    /// 8 for errors, 4 for warnings
+    severity: u8,
+    message: &'a str,
+    start_line_number: usize,
+    start_column: usize,
+    end_line_number: usize,
+    end_column: usize,
+}
+
+/// Convert diagnostics with a file to VS Code problems
+fn vscode_problems<'a>(
+    diagnostics: &'a [Diagnostic],
+    cwd: Option<&Path>,
+) -> Vec<VscodeProblem<'a>> {
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let line = diagnostic.line.unwrap_or(1);
+            let column = diagnostic.column.unwrap_or(1);
+            Some(VscodeProblem {
+                resource: resolve_file(diagnostic.file.as_deref()?, cwd),
+                owner: "tellme",
+                source: &diagnostic.tool,
+                severity: match diagnostic.severity {
+                    Severity::Error => 8,
+                    Severity::Warning => 4,
+                },
+                message: &diagnostic.message,
+                start_line_number: line,
+                start_column: column,
+                end_line_number: line,
+                end_column: column,
+            })
+        })
+        .collect()
+}
+
+/// Render a capture in the given format
+pub fn render(log: &Log, format: LogFormat) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match format {
+        LogFormat::Text => write_log(&mut out, log)?,
+        LogFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, log)?;
+            out.push(b'\n');
+        }
+        LogFormat::Quickfix => write_quickfix(&mut out, log.summary.unwrap_or_default(), log.cwd)?,
+        LogFormat::Vscode => {
+            let problems = vscode_problems(log.summary.unwrap_or_default(), log.cwd);
+            serde_json::to_writer_pretty(&mut out, &problems)?;
+            out.push(b'\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Generate a timestamped log file name
+pub fn default_log_name(format: LogFormat, compression: Compression) -> String {
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+    match compression.extension() {
+        Some(ext) => format!("tellme_{}.{}.{}", timestamp, format.extension(), ext),
+        None => format!("tellme_{}.{}", timestamp, format.extension()),
+    }
+}
+
+/// Save a capture to a log file, returning the number of bytes written
+pub fn save_log(path: &str, log: &Log, format: LogFormat, compression: Compression) -> Result<u64> {
+    let content = compression.compress(&render(log, format)?)?;
+    fs::write(path, &content)?;
+    Ok(content.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::Summarizer;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_default_log_name() {
+        assert!(default_log_name(LogFormat::Text, Compression::None).ends_with(".log"));
+        assert!(default_log_name(LogFormat::Text, Compression::Zstd).ends_with(".log.zst"));
+        assert!(default_log_name(LogFormat::Json, Compression::Gzip).ends_with(".json.gz"));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(LogFormat::from_path(Path::new("a.json")), LogFormat::Json);
+        assert_eq!(
+            LogFormat::from_path(Path::new("a.json.zst")),
+            LogFormat::Json
+        );
+        assert_eq!(LogFormat::from_path(Path::new("a.log.gz")), LogFormat::Text);
+        assert_eq!(
+            LogFormat::from_path(Path::new("errors.err")),
+            LogFormat::Quickfix
+        );
+    }
+
+    #[test]
+    fn test_format_arg() {
+        let json = Some(Path::new("build.json"));
+        // Only `auto` looks at the output name
+        assert_eq!(FormatArg::default().resolve(json), LogFormat::Text);
+        assert_eq!(FormatArg::Auto.resolve(json), LogFormat::Json);
+        assert_eq!(FormatArg::Auto.resolve(None), LogFormat::Text);
+        let vscode = FormatArg::Format(LogFormat::Vscode);
+        assert_eq!(vscode.resolve(json), LogFormat::Vscode);
+
+        // Every format is a value, plus `auto`
+        assert_eq!(
+            FormatArg::from_str("quickfix", false),
+            Ok(FormatArg::Format(LogFormat::Quickfix))
+        );
+        assert_eq!(FormatArg::from_str("auto", false), Ok(FormatArg::Auto));
+        assert_eq!(
+            FormatArg::value_variants().len(),
+            LogFormat::value_variants().len() + 1
+        );
+    }
+
+    #[test]
+    fn test_save_log() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.log.gz");
+        let path = path.to_str().unwrap();
+
+        let written = save_log(
+            path,
+            &Log::new("make", "done"),
+            LogFormat::Text,
+            Compression::Gzip,
+        )
+        .unwrap();
+
+        let data = fs::read(path).unwrap();
+        assert_eq!(written, data.len() as u64);
+        let content = Compression::Gzip.decompress(data).unwrap();
+        assert_eq!(
+            String::from_utf8(content).unwrap(),
+            "Command:\nmake\n=============================\n\ndone\n"
+        );
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let output = "main.c:3:5: error: boom\nmore output";
+        let diagnostics = Summarizer::default().summarize(output);
+        let log = Log::new("make", output).with_summary(&diagnostics);
+
+        let text = String::from_utf8(render(&log, LogFormat::Text).unwrap()).unwrap();
+        assert!(
+            text.starts_with("Command:\nmake\n=============================\n\nSummary: 1 error\n")
+        );
+        assert!(
+            text.ends_with(
+                "=============================\n\nmain.c:3:5: error: boom\nmore output\n"
+            )
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&render(&log, LogFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["command"], "make");
+        assert_eq!(json["summary"][0]["file"], "main.c");
+        assert_eq!(json["summary"][0]["line"], 3);
+        assert_eq!(json["output"], output);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&render(&Log::new("make", ""), LogFormat::Json).unwrap())
+                .unwrap();
+        assert!(json.get("summary").is_none());
+    }
+
+    #[test]
+    fn test_render_summary_only() {
+        let output = "main.c:3:5: error: boom\nmore output";
+        let diagnostics = Summarizer::default().summarize(output);
+        let log = Log::new("make", output)
+            .with_summary(&diagnostics)
+            .without_output();
+
+        let text = String::from_utf8(render(&log, LogFormat::Text).unwrap()).unwrap();
+        assert!(text.ends_with("-----------------------------\nmain.c:3:5: error: boom\n"));
+        assert!(!text.contains("more output"));
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&render(&log, LogFormat::Json).unwrap()).unwrap();
+        assert!(json.get("output").is_none());
+    }
+
+    #[test]
+    fn test_render_editor_formats() {
+        let output = "error[E0308]: mismatched types\n --> src/main.rs:4:18\n\nlib.c:10:1: warning: no return\nnpm ERR! code 1";
+        let diagnostics = Summarizer::default().summarize(output);
+        let log = Log::new("make", output).with_summary(&diagnostics);
+
+        let quickfix = String::from_utf8(render(&log, LogFormat::Quickfix).unwrap()).unwrap();
+        assert_eq!(
+            quickfix,
+            "src/main.rs:4:18: error[E0308]: mismatched types\nlib.c:10:1: warning: no return\n"
+        );
+
+        let problems: serde_json::Value =
+            serde_json::from_slice(&render(&log, LogFormat::Vscode).unwrap()).unwrap();
+        assert_eq!(problems.as_array().unwrap().len(), 2);
+        assert_eq!(problems[0]["resource"], "src/main.rs");
+        assert_eq!(problems[0]["severity"], 8);
+        assert_eq!(problems[0]["startLineNumber"], 4);
+        assert_eq!(problems[0]["startColumn"], 18);
+        assert_eq!(problems[1]["severity"], 4);
+        assert_eq!(problems[1]["source"], "gcc");
+    }
+
+    #[test]
+    fn test_editor_formats_resolve_paths() {
+        let output = "src/lib.rs:4:18: error: boom
+/usr/include/stdio.h:1:1: warning: odd";
+        let diagnostics = Summarizer::default().summarize(output);
+        let log = Log::new("make", output)
+            .with_summary(&diagnostics)
+            .with_cwd(Path::new("/home/me/project"));
+
+        let quickfix = String::from_utf8(render(&log, LogFormat::Quickfix).unwrap()).unwrap();
+        assert_eq!(
+            quickfix,
+            "/home/me/project/src/lib.rs:4:18: error: boom\n\
+             /usr/include/stdio.h:1:1: warning: odd\n"
+        );
+
+        let problems: serde_json::Value =
+            serde_json::from_slice(&render(&log, LogFormat::Vscode).unwrap()).unwrap();
+        assert_eq!(problems[0]["resource"], "/home/me/project/src/lib.rs");
+        assert_eq!(problems[1]["resource"], "/usr/include/stdio.h");
+    }
+}
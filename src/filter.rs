@@ -1,5 +1,9 @@
 use crate::config::Config;
 
+/// tellme itself is never captured: preparing a capture truncates the output
+/// that `tellme` is about to read.
+const SELF_COMMAND: &str = "tellme";
+
 /// Command filtering logic for tellme
 ///
 /// Determines whether a command should have its output captured
@@ -30,6 +34,10 @@ impl CommandFilter {
         // Get the base command (first word)
         let base_cmd = command.split_whitespace().next().unwrap_or("");
 
+        if base_cmd == SELF_COMMAND {
+            return false;
+        }
+
         // Check if any skip pattern matches
         // Support both exact match and prefix match
         !self.skip_commands.iter().any(|skip| {
@@ -77,6 +85,16 @@ mod tests {
         assert!(filter.should_capture("pytest -v tests/"));
     }
 
+    #[test]
+    fn test_never_capture_self() {
+        let config = create_test_config();
+        config.save_skip_commands(&[]).unwrap();
+        let filter = CommandFilter::new(&config);
+
+        assert!(!filter.should_capture("tellme show"));
+        assert!(filter.should_capture("tellmemore"));
+    }
+
     #[test]
     fn test_empty_command() {
         let config = create_test_config();
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::time::Duration;
+
+use crate::error::{Result, TellmeError};
+use crate::meta::CaptureMeta;
+use crate::store::{CaptureRecord, CaptureStore};
+
+/// How often `tellme follow` checks the capture for new output
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Strips ANSI codes from a byte stream, one complete line at a time
+///
+/// Holding back partial lines keeps escape sequences that are split across
+/// reads intact.
+#[derive(Debug, Default)]
+struct CleanStream {
+    pending: Vec<u8>,
+}
+
+impl CleanStream {
+    /// Add bytes and write out every complete line
+    fn push(&mut self, bytes: &[u8], out: &mut impl Write) -> std::io::Result<()> {
+        self.pending.extend_from_slice(bytes);
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<u8> = self.pending.drain(..=end).collect();
+            out.write_all(&strip_ansi_escapes::strip(lines))?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write out the remaining partial line
+    fn finish(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(&strip_ansi_escapes::strip(&self.pending))?;
+        self.pending.clear();
+        out.flush()
+    }
+}
+
+/// Read a file from `offset` to its end
+fn read_from(file: &mut File, offset: u64) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Stream the output of a capture to `out` until its command finishes
+///
+/// Output is written without ANSI codes as it grows. Returns the final
+/// metadata, or `None` if the capture disappeared before it finished.
+pub fn follow(
+    store: &CaptureStore,
+    record: &CaptureRecord,
+    out: &mut impl Write,
+    interval: Duration,
+) -> Result<Option<CaptureMeta>> {
+    let mut stream = CleanStream::default();
+    let mut consumed = 0;
+    let mut inode = None;
+    let write_failed = || TellmeError::io("Cannot write followed output");
+    let read_failed =
+        || TellmeError::io(format!("Cannot read output of capture {}", record.meta.id));
+
+    loop {
+        let finished = match record.reload_meta() {
+            Some(meta) => meta.id != record.meta.id || meta.is_complete(),
+            None => true,
+        };
+
+        if finished {
+            // The output may have been compressed or moved to the history since
+            let Some(last) = store.find(&record.meta.id)? else {
+                stream.finish(out).map_err(write_failed())?;
+                return Ok(None);
+            };
+            let data = last.read_output()?;
+            stream
+                .push(data.get(consumed..).unwrap_or_default(), out)
+                .map_err(write_failed())?;
+            stream.finish(out).map_err(write_failed())?;
+            return Ok(Some(last.meta));
+        }
+
+        if let Ok(mut file) = File::open(record.output_file()) {
+            // Finishing may replace the file with a compressed copy, which
+            // must not be streamed as output; stay with the file being written
+            let current = file.metadata().map_err(read_failed())?.ino();
+            if *inode.get_or_insert(current) == current {
+                let data = read_from(&mut file, consumed as u64).map_err(read_failed())?;
+                consumed += data.len();
+                stream.push(&data, out).map_err(write_failed())?;
+            }
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureSession;
+    use crate::config::test_utils::create_test_config;
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn test_clean_stream_keeps_split_escapes() {
+        let mut stream = CleanStream::default();
+        let mut out = Vec::new();
+
+        stream.push(b"\x1b[31mred\x1b[", &mut out).unwrap();
+        assert!(out.is_empty());
+        stream.push(b"0m\nplain", &mut out).unwrap();
+        assert_eq!(out, b"red\n");
+        stream.finish(&mut out).unwrap();
+        assert_eq!(out, b"red\nplain");
+    }
+
+    #[test]
+    fn test_follow_until_finished() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+        session.prepare_new_command("cargo build").unwrap();
+
+        let store = CaptureStore::new(&config);
+        let record = store.current().unwrap();
+
+        let mut out = Vec::new();
+        let meta = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .open(config.output_file())
+                    .unwrap();
+                file.write_all(b"\x1b[32mCompiling\x1b[0m demo\n").unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                file.write_all(b"Finished").unwrap();
+                session.finish_command(Some(0)).unwrap();
+            });
+
+            follow(&store, &record, &mut out, Duration::from_millis(5)).unwrap()
+        });
+
+        assert_eq!(String::from_utf8(out).unwrap(), "Compiling demo\nFinished");
+        let meta = meta.unwrap();
+        assert_eq!(meta.id, record.meta.id);
+        assert_eq!(meta.exit_code, Some(0));
+    }
+}
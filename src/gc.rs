@@ -0,0 +1,230 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::process;
+use crate::secure;
+use crate::store::{CaptureStore, ShellInfo};
+
+/// Prefix of the marker files naming the process that owns a session
+const MARKER_PREFIX: &str = ".tellme_shell_";
+
+/// How long the files of a session whose shell is unknown are kept unused
+const UNKNOWN_SHELL_GRACE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Contents of a session marker: the shell's PID and when it started
+fn marker(pid: u32, started: &str) -> String {
+    format!("{}\n{}", pid, started)
+}
+
+/// Read the PID and start time recorded for a session
+fn read_marker(config: &Config, session: &str) -> Option<(u32, String)> {
+    let recorded = fs::read_to_string(config.shell_temp_file("shell", session)).ok()?;
+    let (pid, started) = recorded.split_once('\n')?;
+    Some((pid.parse().ok()?, started.trim().to_string()))
+}
+
+/// PID of the shell that owns a session, if it was recorded
+pub(crate) fn session_pid(config: &Config, session: &str) -> Option<u32> {
+    read_marker(config, session).map(|(pid, _)| pid)
+}
+
+/// Record which process owns this session, so a later shell reusing its PID is told apart
+///
+/// Files left under this session by another process are removed first, as
+/// are sessions a shell left behind when it replaced itself with `exec`.
+pub(crate) fn claim_shell(config: &Config) -> Result<()> {
+    let pid = config.pid();
+    let Some(started) = process::start_time(pid) else {
+        return Ok(());
+    };
+    let current = marker(pid, &started);
+
+    match fs::read_to_string(config.shell_file()) {
+        Ok(recorded) if recorded.trim() == current => return Ok(()),
+        Ok(_) => remove_shell_files(config, config.session())?,
+        Err(_) => {}
+    }
+
+    if let Ok(entries) = fs::read_dir(config.temp_path()) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(session) = name
+                .strip_prefix(MARKER_PREFIX)
+                .filter(|session| Config::is_valid_session(session))
+            else {
+                continue;
+            };
+            if session != config.session()
+                && read_marker(config, session) == Some((pid, started.clone()))
+            {
+                remove_shell_files(config, session)?;
+            }
+        }
+    }
+
+    secure::private_dir(config.temp_path())?;
+    secure::write_private(&config.shell_file(), current)
+}
+
+/// Check whether the shell that owns this session is still running
+///
+/// A running process with another start time than the recorded one is a
+/// new shell that reused the PID.
+pub(crate) fn is_shell_alive(config: &Config, session: &str, pid: u32) -> bool {
+    if !process::is_alive(pid) {
+        return false;
+    }
+
+    match read_marker(config, session) {
+        Some((_, recorded)) => process::start_time(pid).is_none_or(|started| started == recorded),
+        // Without a record we cannot tell, so keep the files
+        None => true,
+    }
+}
+
+/// Check whether a session whose shell is unknown was used recently
+///
+/// Commands that only read, such as `tellme show` with recording off, leave
+/// a lock file but no record of the shell, which may well still be running.
+pub(crate) fn is_recently_used(config: &Config, session: &str) -> bool {
+    let mut files = config.temp_files_of(session);
+    files.push(config.history_dir_of(session));
+    files.iter().any(|file| {
+        fs::metadata(file)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| {
+                // A modification time in the future counts as recent
+                SystemTime::now()
+                    .duration_since(modified)
+                    .ok()
+                    .is_none_or(|age| age < UNKNOWN_SHELL_GRACE)
+            })
+    })
+}
+
+/// Remove the temp files and history of this shell session
+pub(crate) fn remove_shell_files(config: &Config, session: &str) -> Result<()> {
+    for file in config.temp_files_of(session) {
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+
+    let history = config.history_dir_of(session);
+    if history.exists() {
+        fs::remove_dir_all(history)?;
+    }
+    Ok(())
+}
+
+/// Remove the files of every shell that is no longer running
+///
+/// Archived captures are kept. Returns the swept shells.
+pub fn sweep(config: &Config) -> Result<Vec<ShellInfo>> {
+    let mut swept = Vec::new();
+
+    for shell in CaptureStore::new(config).shells()? {
+        if !shell.alive {
+            remove_shell_files(config, &shell.session)?;
+            swept.push(shell);
+        }
+    }
+
+    Ok(swept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_utils::{
+        create_test_config, create_test_config_for_shell, run_command,
+    };
+
+    #[test]
+    fn test_sweep_dead_shells() {
+        // The test config's PID 99999 is not a running process
+        let dead = create_test_config();
+        let alive = create_test_config_for_shell(&dead, std::process::id());
+
+        run_command(&dead, "make", "output", 0);
+        run_command(&alive, "ls", "output", 0);
+
+        let swept = sweep(&dead).unwrap();
+        assert_eq!(swept.len(), 1);
+        assert_eq!(swept[0].session, "99999");
+        assert_eq!(swept[0].pid, Some(99999));
+        assert!(!dead.output_file().exists());
+        assert!(!dead.history_dir().exists());
+
+        assert!(alive.output_file().exists());
+        assert!(alive.shell_file().exists());
+        assert!(sweep(&alive).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reused_pid() {
+        let config = create_test_config_for_shell(&create_test_config(), std::process::id());
+        run_command(&config, "make", "output", 0);
+        assert!(is_shell_alive(&config, config.session(), config.pid()));
+
+        // Files from an earlier shell that had the same PID
+        fs::write(
+            config.shell_file(),
+            marker(config.pid(), "another start time"),
+        )
+        .unwrap();
+        assert!(!is_shell_alive(&config, config.session(), config.pid()));
+
+        claim_shell(&config).unwrap();
+        assert!(!config.output_file().exists());
+        assert!(is_shell_alive(&config, config.session(), config.pid()));
+    }
+
+    #[test]
+    fn test_exec_replaces_session() {
+        let mut old = create_test_config_for_shell(&create_test_config(), std::process::id());
+        old.attach("old-session", std::process::id());
+        run_command(&old, "make", "output", 0);
+        assert_eq!(session_pid(&old, "old-session"), Some(std::process::id()));
+
+        // `exec zsh` keeps the process but starts a new session
+        let mut new = old.clone();
+        new.attach("new-session", std::process::id());
+        run_command(&new, "ls", "output", 0);
+
+        assert!(!old.output_file().exists());
+        assert!(!old.history_dir().exists());
+        assert!(new.output_file().exists());
+    }
+
+    #[test]
+    fn test_unknown_shell_kept_while_recent() {
+        let mut config = create_test_config();
+        config.attach("reader-session", 0);
+        let lock = config.lock_file();
+        secure::private_dir(config.temp_path()).unwrap();
+        secure::write_private(&lock, "").unwrap();
+
+        // Only a lock file: the shell that read through it may still be running
+        let shells = CaptureStore::new(&config).shells().unwrap();
+        assert_eq!(shells.len(), 1);
+        assert_eq!(shells[0].pid, None);
+        assert!(shells[0].alive);
+        assert!(sweep(&config).unwrap().is_empty());
+        assert!(lock.exists());
+
+        let old = SystemTime::now() - UNKNOWN_SHELL_GRACE - Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&lock)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let swept = sweep(&config).unwrap();
+        assert_eq!(swept.len(), 1);
+        assert_eq!(swept[0].session, "reader-session");
+        assert!(!lock.exists());
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::error::{Result, TellmeError};
+use crate::meta::CaptureMeta;
+use crate::process;
+use crate::settings::Hook;
+
+/// How often a running hook is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What a hook is told about, sent as JSON on its stdin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookEvent {
+    /// The hook being run
+    pub hook: Hook,
+
+    /// The capture the event is about
+    pub capture: CaptureMeta,
+
+    /// How long the command ran, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+
+    /// Where the capture was saved, for `on_save`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_path: Option<PathBuf>,
+}
+
+impl HookEvent {
+    /// Create an event about a capture
+    pub fn new(hook: Hook, capture: &CaptureMeta) -> Self {
+        Self {
+            hook,
+            capture: capture.clone(),
+            duration_ms: capture.duration().map(|d| d.as_millis() as u64),
+            saved_path: None,
+        }
+    }
+
+    /// Environment variables describing the event; unknown values are empty
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let capture = &self.capture;
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        vec![
+            ("TELLME_HOOK", self.hook.name().to_string()),
+            ("TELLME_ID", capture.id.clone()),
+            ("TELLME_COMMAND", capture.command.clone()),
+            ("TELLME_CWD", capture.cwd.display().to_string()),
+            (
+                "TELLME_EXIT_CODE",
+                optional(capture.exit_code.map(|c| c.to_string())),
+            ),
+            (
+                "TELLME_DURATION_MS",
+                optional(self.duration_ms.map(|d| d.to_string())),
+            ),
+            ("TELLME_SIZE", capture.size.to_string()),
+            (
+                "TELLME_SAVED_PATH",
+                optional(self.saved_path.as_ref().map(|p| p.display().to_string())),
+            ),
+        ]
+    }
+}
+
+/// Start a detached `tellme internal --run-hook` for each event with a hook
+pub fn spawn(config: &Config, events: &[HookEvent]) -> Result<()> {
+    let settings = &config.settings().hooks;
+    for event in events {
+        if settings.command(event.hook).is_some() {
+            process::spawn_detached(&["internal", "--run-hook"], &serde_json::to_vec(event)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run a hook command for an event, killing it after `timeout`
+///
+/// Returns the exit status of the hook, or `None` if it was killed.
+pub fn run(command: &str, event: &HookEvent, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let mut hook = Command::new("sh");
+    hook.arg("-c")
+        .arg(command)
+        .envs(event.env())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0);
+    if event.capture.cwd.is_dir() {
+        hook.current_dir(&event.capture.cwd);
+    }
+    let mut child = hook.spawn().map_err(TellmeError::io(format!(
+        "Cannot run the {} hook",
+        event.hook.name()
+    )))?;
+
+    // A hook that never reads its stdin must not keep us from the timeout
+    let payload = serde_json::to_vec(event)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        std::thread::spawn(move || stdin.write_all(&payload).ok());
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            // The whole group, so programs started by the hook go too
+            unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn failed_capture() -> CaptureMeta {
+        let mut meta = CaptureMeta::new("make test", "s1", 42);
+        meta.finish(Some(2), 128);
+        meta
+    }
+
+    #[test]
+    fn test_run_passes_env_and_json() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out");
+        let event = HookEvent::new(Hook::OnFailure, &failed_capture());
+
+        let command = format!(
+            "{{ echo \"$TELLME_HOOK $TELLME_COMMAND $TELLME_EXIT_CODE $TELLME_SIZE\"; cat; }} > '{}'",
+            out.display()
+        );
+        let status = run(&command, &event, Duration::from_secs(10)).unwrap();
+        assert!(status.unwrap().success());
+
+        let written = fs::read_to_string(&out).unwrap();
+        let (line, json) = written.split_once('\n').unwrap();
+        assert_eq!(line, "on_failure make test 2 128");
+        let received: HookEvent = serde_json::from_str(json).unwrap();
+        assert_eq!(received, event);
+    }
+
+    #[test]
+    fn test_run_kills_slow_hook() {
+        let event = HookEvent::new(Hook::OnCaptureComplete, &failed_capture());
+
+        let start = Instant::now();
+        let status = run("sleep 5", &event, Duration::from_millis(100)).unwrap();
+        assert!(status.is_none());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_event_env() {
+        let mut event = HookEvent::new(Hook::OnSave, &CaptureMeta::new("ls", "s1", 1));
+        event.saved_path = Some(PathBuf::from("/tmp/ls.log"));
+
+        let env = event.env();
+        let var = |name| env.iter().find(|(n, _)| *n == name).unwrap().1.as_str();
+        assert_eq!(var("TELLME_HOOK"), "on_save");
+        assert_eq!(var("TELLME_EXIT_CODE"), "");
+        assert_eq!(var("TELLME_DURATION_MS"), "");
+        assert_eq!(var("TELLME_SAVED_PATH"), "/tmp/ls.log");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::error::{Result, TellmeError};
+use std::time::Duration;
+
+/// Format a byte count for display (e.g. `512B`, `1.5K`, `12.0M`)
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G"];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    for unit in &UNITS[..UNITS.len() - 1] {
+        if size < 1024.0 {
+            return format!("{:.1}{}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1}{}", size, UNITS[UNITS.len() - 1])
+}
+
+/// Format a duration for display (e.g. `0.4s`, `3m04s`, `1h02m`)
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Parse a duration such as `30s`, `10m`, `1h`, `2d` or `1w`
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| TellmeError::InvalidInput(format!("Invalid duration '{}'", text)))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(TellmeError::InvalidInput(format!(
+                "Invalid duration unit '{}' (use s, m, h, d or w)",
+                unit
+            )));
+        }
+    };
+
+    Ok(Duration::from_secs(number * multiplier))
+}
+
+/// Shorten text to at most `max` characters, marking the cut with `…`
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let mut short: String = text.chars().take(max.saturating_sub(1)).collect();
+        short.push('…');
+        short
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(12 * 1024 * 1024), "12.0M");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(400)), "0.4s");
+        assert_eq!(format_duration(Duration::from_secs(184)), "3m04s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h02m");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::from_secs(172800));
+        assert!(parse_duration("1y").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("cargo", 10), "cargo");
+        assert_eq!(truncate("cargo build --release", 10), "cargo bui…");
+    }
+}
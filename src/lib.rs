@@ -0,0 +1,44 @@
+//! tellme: a terminal black box that captures the output of shell commands
+//!
+//! Besides the `tellme` binary, this crate lets other tools read captures
+//! without scraping the CLI output. With [`Config::new`], paths and the
+//! current shell come from the same `TELLME_*` environment variables the
+//! binary uses; [`Config::from_dirs`] takes them explicitly instead.
+//!
+//! ```no_run
+//! use tellme::{CaptureStore, Config};
+//!
+//! let config = Config::new()?;
+//! let store = CaptureStore::new(&config);
+//! for record in store.list()?.iter().take(5) {
+//!     println!("{} {}", record.meta.id, record.meta.command);
+//! }
+//! if let Some(last) = store.list()?.first() {
+//!     let output = last.read_output()?;
+//!     println!("{}", String::from_utf8_lossy(&output));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod archive;
+pub mod capture;
+pub mod compress;
+pub mod config;
+pub mod error;
+pub mod filter;
+pub mod follow;
+pub mod gc;
+mod lock;
+pub mod meta;
+mod process;
+mod secure;
+pub mod settings;
+pub mod store;
+pub mod summary;
+
+pub use capture::CaptureSession;
+pub use config::Config;
+pub use error::{Result, TellmeError};
+pub use filter::CommandFilter;
+pub use meta::CaptureMeta;
+pub use store::{CaptureQuery, CaptureRecord, CaptureSource, CaptureStore, ShellInfo};
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use crate::error::{Result, TellmeError};
+use crate::secure;
+
+/// An advisory lock on a file, released when dropped
+///
+/// Only processes that take the lock are kept out; it does not stop the
+/// shell's `tee` from writing the output file.
+#[derive(Debug)]
+pub struct FileLock {
+    /// The locked file; closing it releases the lock
+    _file: File,
+}
+
+impl FileLock {
+    /// Wait for an exclusive lock, for writing
+    pub fn exclusive(path: &Path) -> Result<Self> {
+        Self::acquire(path, libc::LOCK_EX)
+    }
+
+    /// Wait for a shared lock, for reading
+    pub fn shared(path: &Path) -> Result<Self> {
+        Self::acquire(path, libc::LOCK_SH)
+    }
+
+    fn acquire(path: &Path, operation: libc::c_int) -> Result<Self> {
+        let file = secure::open_private(path)?;
+        loop {
+            // SAFETY: the descriptor is open for the lifetime of `file`
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(Self { _file: file });
+            }
+            let error = std::io::Error::last_os_error();
+            if error.kind() != std::io::ErrorKind::Interrupted {
+                return Err(TellmeError::io(format!("Cannot lock {}", path.display()))(
+                    error,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_exclusive_lock_waits() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("lock");
+
+        let first = FileLock::shared(&path).unwrap();
+        // Shared locks do not exclude each other
+        let second = FileLock::shared(&path).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let writer_path = path.clone();
+        let writer = std::thread::spawn(move || {
+            let _lock = FileLock::exclusive(&writer_path).unwrap();
+            sender.send(()).unwrap();
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(first);
+        drop(second);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+    }
+}
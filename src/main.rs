@@ -1,8 +1,45 @@
 mod app;
+mod archive;
+mod browse;
 mod capture;
+mod clipboard;
+mod compress;
 mod config;
+mod diff;
+mod error;
+mod export;
 mod filter;
+mod follow;
+mod gc;
+mod hooks;
+mod humanize;
+mod lock;
+mod meta;
+mod notify;
+mod process;
+mod redact;
+mod rerun;
+mod search;
+mod secure;
+mod select;
+mod settings;
+mod share;
+mod store;
+mod summary;
 
-fn main() -> anyhow::Result<()> {
-    crate::app::app()
+use colored::Colorize;
+use error::TellmeError;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match app::app() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", format!("Error: {}.", e).red());
+            ExitCode::from(
+                e.downcast_ref::<TellmeError>()
+                    .map_or(1, TellmeError::exit_code),
+            )
+        }
+    }
 }
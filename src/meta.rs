@@ -0,0 +1,185 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::compress::Compression;
+
+/// Metadata recorded for every capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureMeta {
+    /// Unique capture id: 32 hex digits, or 8 for captures of older versions
+    pub id: String,
+
+    /// The exact command line
+    pub command: String,
+
+    /// Working directory the command ran in
+    pub cwd: PathBuf,
+
+    /// Session id of the shell that ran the command
+    #[serde(default)]
+    pub session: String,
+
+    /// PID of the shell that ran the command, for information only
+    pub shell_pid: u32,
+
+    /// When the command started
+    pub started_at: DateTime<Local>,
+
+    /// When the command finished, if it has
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Local>>,
+
+    /// Exit code of the command, if known
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+
+    /// Size of the uncompressed output in bytes
+    #[serde(default)]
+    pub size: u64,
+
+    /// Pinned captures are never removed by retention rules
+    #[serde(default)]
+    pub pinned: bool,
+
+    /// Environment variables recorded when the command started
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// How the stored output is compressed; `None` for captures recorded
+    /// before this was tracked, whose format is detected instead
+    #[serde(default)]
+    pub compression: Option<Compression>,
+}
+
+impl CaptureMeta {
+    /// Create metadata for a command that is about to start
+    pub fn new(command: &str, session: &str, shell_pid: u32) -> Self {
+        Self {
+            id: Self::generate_id(),
+            command: command.to_string(),
+            cwd: std::env::current_dir().unwrap_or_default(),
+            session: session.to_string(),
+            shell_pid,
+            started_at: Local::now(),
+            finished_at: None,
+            exit_code: None,
+            size: 0,
+            pinned: false,
+            env: BTreeMap::new(),
+            // The shell writes plain output; it may be compressed once finished
+            compression: Some(Compression::None),
+        }
+    }
+
+    /// The session that ran the command
+    ///
+    /// Captures recorded before sessions existed are keyed by the shell PID.
+    pub fn session_key(&self) -> String {
+        if self.session.is_empty() {
+            self.shell_pid.to_string()
+        } else {
+            self.session.clone()
+        }
+    }
+
+    /// Record the current values of the given environment variables
+    pub fn capture_env(&mut self, names: &[String]) {
+        self.env = names
+            .iter()
+            .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+            .collect();
+    }
+
+    /// Generate a random capture id
+    fn generate_id() -> String {
+        uuid::Uuid::new_v4().simple().to_string()
+    }
+
+    /// The first 8 digits of the id, for display; any unique prefix finds it
+    pub fn short_id(&self) -> &str {
+        self.id.get(..8).unwrap_or(&self.id)
+    }
+
+    /// Mark the capture as finished
+    pub fn finish(&mut self, exit_code: Option<i32>, size: u64) {
+        self.finished_at = Some(Local::now());
+        self.exit_code = exit_code;
+        self.size = size;
+    }
+
+    /// Whether the command has finished
+    pub fn is_complete(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    /// Whether the command finished with a non-zero exit code
+    pub fn failed(&self) -> bool {
+        matches!(self.exit_code, Some(code) if code != 0)
+    }
+
+    /// How long the command ran, if it has finished
+    pub fn duration(&self) -> Option<Duration> {
+        self.finished_at
+            .and_then(|end| (end - self.started_at).to_std().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_meta() {
+        let meta = CaptureMeta::new("cargo build", "s1", 42);
+        assert_eq!(meta.command, "cargo build");
+        assert_eq!(meta.session_key(), "s1");
+        assert_eq!(meta.shell_pid, 42);
+        assert_eq!(meta.id.len(), 32);
+        assert_eq!(meta.short_id(), &meta.id[..8]);
+        assert!(!meta.is_complete());
+        assert!(meta.duration().is_none());
+    }
+
+    #[test]
+    fn test_capture_env() {
+        let mut meta = CaptureMeta::new("env", "s1", 1);
+        meta.capture_env(&["PATH".to_string(), "TELLME_SURELY_UNSET".to_string()]);
+        assert!(meta.env.contains_key("PATH"));
+        assert!(!meta.env.contains_key("TELLME_SURELY_UNSET"));
+    }
+
+    #[test]
+    fn test_unique_ids() {
+        let a = CaptureMeta::new("a", "s1", 1);
+        let b = CaptureMeta::new("a", "s1", 1);
+        assert_ne!(a.id, b.id);
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut meta = CaptureMeta::new("false", "s1", 42);
+        meta.finish(Some(1), 10);
+        assert!(meta.is_complete());
+        assert!(meta.failed());
+        assert_eq!(meta.size, 10);
+        assert!(meta.duration().is_some());
+
+        meta.finish(Some(0), 10);
+        assert!(!meta.failed());
+    }
+
+    #[test]
+    fn test_session_key_of_old_meta() {
+        let mut meta = CaptureMeta::new("ls", "", 42);
+        assert_eq!(meta.session_key(), "42");
+
+        // Metadata written before sessions existed has no session field
+        let mut json = serde_json::to_value(&meta).unwrap();
+        json.as_object_mut().unwrap().remove("session");
+        meta = serde_json::from_value(json).unwrap();
+        assert_eq!(meta.session_key(), "42");
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::{Result, TellmeError};
+use crate::humanize;
+use crate::meta::CaptureMeta;
+use crate::process;
+use crate::settings::{NotifyBackend, NotifySettings};
+
+/// Longest command shown in a notification
+const MAX_COMMAND_CHARS: usize = 120;
+
+/// A desktop notification about a finished command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Headline: how the command ended and how long it took
+    pub summary: String,
+
+    /// The command, shortened to fit
+    pub body: String,
+
+    /// Themed icon name, e.g. `dialog-error`
+    pub icon: &'static str,
+}
+
+impl Notification {
+    /// Describe a finished capture: its command, status and duration
+    pub fn for_capture(meta: &CaptureMeta) -> Self {
+        let duration = humanize::format_duration(meta.duration().unwrap_or_default());
+        let (summary, icon) = match meta.exit_code {
+            Some(0) => (format!("Finished in {}", duration), "dialog-information"),
+            Some(code) => (
+                format!("Failed with exit {} after {}", code, duration),
+                "dialog-error",
+            ),
+            None => (format!("Finished after {}", duration), "dialog-information"),
+        };
+        Self {
+            summary,
+            body: humanize::truncate(&meta.command, MAX_COMMAND_CHARS),
+            icon,
+        }
+    }
+}
+
+/// Whether a finished capture ran long enough to notify about
+pub fn is_due(settings: &NotifySettings, meta: &CaptureMeta) -> bool {
+    settings.enabled
+        && meta
+            .duration()
+            .is_some_and(|d| d >= Duration::from_secs(settings.min_duration_secs))
+}
+
+/// Start a detached `tellme internal --notify` if the capture is due one
+pub fn spawn(config: &Config, meta: &CaptureMeta) -> Result<()> {
+    if is_due(&config.settings().notify, meta) {
+        process::spawn_detached(&["internal", "--notify"], &serde_json::to_vec(meta)?)?;
+    }
+    Ok(())
+}
+
+/// Something that can show a notification
+trait Backend {
+    /// Name of the backend, for error messages
+    fn describe(&self) -> String;
+
+    /// Show the notification, or explain why it cannot be shown
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// The freedesktop notification service, called over D-Bus
+struct Dbus {
+    /// Address of the bus, or `None` for the session bus
+    address: Option<String>,
+}
+
+impl Backend for Dbus {
+    fn describe(&self) -> String {
+        "D-Bus".to_string()
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let failed = |e: zbus::Error| TellmeError::NotificationFailed(e.to_string());
+        let builder = match &self.address {
+            Some(address) => zbus::blocking::connection::Builder::address(address.as_str()),
+            None => zbus::blocking::connection::Builder::session(),
+        };
+        let connection = builder.and_then(|b| b.build()).map_err(failed)?;
+        let hints: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+        connection
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &(
+                    "tellme",
+                    0u32,
+                    notification.icon,
+                    &notification.summary,
+                    &notification.body,
+                    Vec::<&str>::new(),
+                    hints,
+                    // The server's default timeout
+                    -1i32,
+                ),
+            )
+            .map_err(failed)?;
+        Ok(())
+    }
+}
+
+/// The `notify-send` command from libnotify
+struct NotifySend {
+    /// Program to run, `notify-send` from the PATH unless testing
+    program: PathBuf,
+}
+
+impl Backend for NotifySend {
+    fn describe(&self) -> String {
+        "notify-send".to_string()
+    }
+
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let status = Command::new(&self.program)
+            .args(["--app-name=tellme", "--icon", notification.icon, "--"])
+            .arg(&notification.summary)
+            .arg(&notification.body)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| {
+                TellmeError::NotificationFailed(format!("cannot run notify-send: {}", e))
+            })?;
+        if !status.success() {
+            return Err(TellmeError::NotificationFailed(format!(
+                "notify-send failed ({})",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Shows notifications with the first backend that works
+pub struct Notifier {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Notifier {
+    /// Use the backend from the settings, or D-Bus with a `notify-send` fallback
+    pub fn new(settings: &NotifySettings) -> Self {
+        let dbus = || Box::new(Dbus { address: None });
+        let notify_send = || {
+            Box::new(NotifySend {
+                program: PathBuf::from("notify-send"),
+            })
+        };
+        let backends: Vec<Box<dyn Backend>> = match settings.backend {
+            NotifyBackend::Auto => vec![dbus(), notify_send()],
+            NotifyBackend::Dbus => vec![dbus()],
+            NotifyBackend::NotifySend => vec![notify_send()],
+        };
+        Self { backends }
+    }
+
+    /// Show a notification, returning the backend that showed it
+    ///
+    /// Fails only when no backend could, listing why each one failed.
+    pub fn notify(&self, notification: &Notification) -> Result<String> {
+        let mut reasons = Vec::new();
+        for backend in &self.backends {
+            match backend.notify(notification) {
+                Ok(()) => return Ok(backend.describe()),
+                Err(TellmeError::NotificationFailed(reason)) => {
+                    reasons.push(format!("{}: {}", backend.describe(), reason))
+                }
+                Err(e) => reasons.push(format!("{}: {}", backend.describe(), e)),
+            }
+        }
+        Err(TellmeError::NotificationFailed(reasons.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    fn finished(command: &str, exit_code: i32, secs: i64) -> CaptureMeta {
+        let mut meta = CaptureMeta::new(command, "s1", 42);
+        meta.finish(Some(exit_code), 0);
+        meta.started_at = meta.finished_at.unwrap() - TimeDelta::seconds(secs);
+        meta
+    }
+
+    #[test]
+    fn test_is_due() {
+        let settings = NotifySettings {
+            enabled: true,
+            min_duration_secs: 60,
+            ..NotifySettings::default()
+        };
+        assert!(is_due(&settings, &finished("make", 0, 90)));
+        assert!(!is_due(&settings, &finished("make", 1, 10)));
+        assert!(!is_due(
+            &NotifySettings::default(),
+            &finished("make", 0, 90)
+        ));
+        // Still running
+        assert!(!is_due(&settings, &CaptureMeta::new("make", "s1", 42)));
+    }
+
+    #[test]
+    fn test_notification_text() {
+        let failed = Notification::for_capture(&finished("cargo build --release", 101, 1213));
+        assert_eq!(failed.summary, "Failed with exit 101 after 20m13s");
+        assert_eq!(failed.body, "cargo build --release");
+        assert_eq!(failed.icon, "dialog-error");
+
+        let done = Notification::for_capture(&finished("make", 0, 75));
+        assert_eq!(done.summary, "Finished in 1m15s");
+        assert_eq!(done.icon, "dialog-information");
+    }
+
+    #[test]
+    fn test_falls_back_to_notify_send() {
+        let dir = tempdir().unwrap();
+        let shown = dir.path().join("shown");
+        let program = dir.path().join("notify-send");
+        fs::write(
+            &program,
+            format!("#!/bin/sh\nprintf '%s\\n' \"$@\" > '{}'\n", shown.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        // No notification service listens on this bus
+        let no_bus = || {
+            Box::new(Dbus {
+                address: Some(format!("unix:path={}", dir.path().join("bus").display())),
+            })
+        };
+
+        let notifier = Notifier {
+            backends: vec![no_bus(), Box::new(NotifySend { program })],
+        };
+        let notification = Notification::for_capture(&finished("cargo test", 101, 75));
+        assert_eq!(notifier.notify(&notification).unwrap(), "notify-send");
+        assert_eq!(
+            fs::read_to_string(&shown).unwrap(),
+            "--app-name=tellme\n--icon\ndialog-error\n--\nFailed with exit 101 after 1m15s\ncargo test\n"
+        );
+
+        let broken = Notifier {
+            backends: vec![
+                no_bus(),
+                Box::new(NotifySend {
+                    program: dir.path().join("missing"),
+                }),
+            ],
+        };
+        let error = broken.notify(&notification).unwrap_err();
+        assert_eq!(error.exit_code(), 10);
+        let message = error.to_string();
+        assert!(message.contains("D-Bus: "), "{}", message);
+        assert!(
+            message.contains("notify-send: cannot run notify-send"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+use crate::error::{Result, TellmeError};
+
+/// Check whether a process with this PID is running
+pub fn is_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        std::path::Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        // `kill -0` only checks that the process exists and can be signalled
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+/// An identifier of when a process started, if it is running
+///
+/// Two processes with the same PID have different start times, so this
+/// tells a shell apart from a later one that reused its PID.
+pub fn start_time(pid: u32) -> Option<String> {
+    if cfg!(target_os = "linux") {
+        // Field 22 of /proc/<pid>/stat, in clock ticks since boot. The command
+        // name in field 2 may contain spaces, so count from its closing paren.
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        let ticks = fields.get(19)?;
+        let boot_id =
+            std::fs::read_to_string("/proc/sys/kernel/random/boot_id").unwrap_or_default();
+        Some(format!("{}:{}", boot_id.trim(), ticks))
+    } else {
+        let output = Command::new("ps")
+            .args(["-o", "lstart=", "-p", &pid.to_string()])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let started = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (!started.is_empty()).then_some(started)
+    }
+}
+
+/// Start `tellme` with these arguments in the background, with `input` on stdin
+///
+/// Returns without waiting: the process outlives this one, so slow work
+/// never holds up the shell.
+pub fn spawn_detached(args: &[&str], input: &[u8]) -> Result<()> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Out of the shell's process group, so Ctrl-C at the prompt spares it
+        .process_group(0)
+        .spawn()
+        .map_err(TellmeError::io("Cannot start a background tellme"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_alive() {
+        assert!(is_alive(std::process::id()));
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_alive(pid));
+    }
+
+    #[test]
+    fn test_start_time() {
+        let own = start_time(std::process::id());
+        assert!(own.is_some());
+        assert_eq!(own, start_time(std::process::id()));
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(start_time(pid).is_none());
+    }
+}
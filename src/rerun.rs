@@ -0,0 +1,157 @@
+use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::capture::CaptureSession;
+use crate::config::Config;
+use crate::error::{Result, TellmeError};
+use crate::meta::CaptureMeta;
+
+/// Exit code of a process, using the shell convention `128 + signal` for signals
+fn exit_code(status: ExitStatus) -> Option<i32> {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+}
+
+/// Run a captured command again and record it as the shell's last capture
+///
+/// The command runs through `$SHELL -c` in its recorded working directory,
+/// with the recorded environment variables applied when `with_env` is set.
+/// Its combined stdout and stderr are streamed to `echo` while being captured.
+/// The new capture belongs to the shell running tellme, not to a shell the
+/// config is attached to with `--shell`.
+///
+/// Returns the metadata and output of the new capture.
+pub fn rerun(
+    config: &Config,
+    previous: &CaptureMeta,
+    with_env: bool,
+    echo: &mut impl Write,
+) -> Result<(CaptureMeta, Vec<u8>)> {
+    if !previous.cwd.is_dir() {
+        return Err(TellmeError::NotFound(format!(
+            "Working directory {} no longer exists",
+            previous.cwd.display()
+        )));
+    }
+
+    let config = &config.invoking_shell();
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    let (mut reader, writer) = std::io::pipe()?;
+
+    let mut command = Command::new(shell);
+    command
+        .arg("-c")
+        .arg(&previous.command)
+        .current_dir(&previous.cwd)
+        .stdin(Stdio::inherit())
+        .stdout(writer.try_clone()?)
+        .stderr(writer);
+    if with_env {
+        command.envs(&previous.env);
+    }
+
+    let mut meta = CaptureMeta::new(&previous.command, config.session(), config.pid());
+    meta.cwd = previous.cwd.clone();
+    if with_env {
+        meta.env = previous.env.clone();
+    } else {
+        meta.capture_env(&config.settings().capture.env);
+    }
+
+    let mut child = command.spawn()?;
+    // Drop our ends of the pipe so reading stops when the command exits
+    drop(command);
+
+    let mut output = Vec::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        echo.write_all(&buffer[..n])?;
+        echo.flush()?;
+        output.extend_from_slice(&buffer[..n]);
+    }
+
+    let status = child.wait()?;
+    meta.finish(exit_code(status), output.len() as u64);
+    CaptureSession::new(config).record(&meta, &output)?;
+
+    Ok((meta, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_utils::create_test_config_with_tempdir;
+    use crate::store::CaptureStore;
+
+    #[test]
+    fn test_rerun_records_new_capture() {
+        let (config, temp_dir) = create_test_config_with_tempdir();
+        let mut previous =
+            CaptureMeta::new("pwd; echo oops >&2; exit 3", config.session(), config.pid());
+        previous.cwd = temp_dir.path().to_path_buf();
+
+        let mut echo = Vec::new();
+        let (meta, output) = rerun(&config, &previous, false, &mut echo).unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.contains(temp_dir.path().file_name().unwrap().to_str().unwrap()));
+        assert!(text.contains("oops"));
+        assert_eq!(echo, text.as_bytes());
+        assert_eq!(meta.exit_code, Some(3));
+        assert_ne!(meta.id, previous.id);
+
+        // The new run is now the shell's last capture
+        let current = CaptureStore::new(&config).current().unwrap();
+        assert_eq!(current.meta.id, meta.id);
+        assert_eq!(current.read_output().unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_rerun_with_env() {
+        let (config, temp_dir) = create_test_config_with_tempdir();
+        let mut previous =
+            CaptureMeta::new("echo $TELLME_RERUN_VALUE", config.session(), config.pid());
+        previous.cwd = temp_dir.path().to_path_buf();
+        previous
+            .env
+            .insert("TELLME_RERUN_VALUE".to_string(), "recorded".to_string());
+
+        let (_, output) = rerun(&config, &previous, true, &mut Vec::new()).unwrap();
+        assert_eq!(output, b"recorded\n");
+
+        let (_, output) = rerun(&config, &previous, false, &mut Vec::new()).unwrap();
+        assert_eq!(output, b"\n");
+    }
+
+    #[test]
+    fn test_rerun_records_in_invoking_shell() {
+        let (config, temp_dir) = create_test_config_with_tempdir();
+        let mut attached = config.clone();
+        attached.attach("other-session", 4242);
+
+        let mut previous = CaptureMeta::new("echo again", "other-session", 4242);
+        previous.cwd = temp_dir.path().to_path_buf();
+        let (meta, _) = rerun(&attached, &previous, false, &mut Vec::new()).unwrap();
+        assert_eq!(meta.session, config.session());
+        assert_eq!(meta.shell_pid, config.pid());
+
+        let store = CaptureStore::new(&config);
+        assert_eq!(store.current().unwrap().meta.id, meta.id);
+        assert!(CaptureStore::new(&attached).current().is_none());
+    }
+
+    #[test]
+    fn test_rerun_missing_cwd() {
+        let (config, temp_dir) = create_test_config_with_tempdir();
+        let mut previous = CaptureMeta::new("true", config.session(), config.pid());
+        previous.cwd = temp_dir.path().join("gone");
+
+        assert!(rerun(&config, &previous, false, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::error::{Result, TellmeError};
+
+/// A line of output reported by a search
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchLine<'t> {
+    /// 1-based line number
+    pub number: usize,
+
+    /// The line text, without the trailing newline
+    pub text: &'t str,
+
+    /// Whether the line matched, as opposed to being context
+    pub is_match: bool,
+}
+
+/// Options for building a search pattern
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatternOptions {
+    /// Match regardless of case
+    pub ignore_case: bool,
+
+    /// Treat the pattern as a literal string instead of a regex
+    pub fixed_strings: bool,
+}
+
+/// Build a regex from a user pattern
+pub fn build_pattern(pattern: &str, options: PatternOptions) -> Result<Regex> {
+    let pattern = if options.fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .map_err(|e| TellmeError::InvalidInput(format!("Invalid pattern: {}", e)))
+}
+
+/// Search text line by line
+///
+/// Returns groups of adjacent lines: each match with up to `context` lines
+/// before and after it. Overlapping groups are merged.
+pub fn search<'t>(text: &'t str, regex: &Regex, context: usize) -> Vec<Vec<SearchLine<'t>>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut groups: Vec<Vec<SearchLine>> = Vec::new();
+    let mut last_end = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+
+        // Start a new group unless this one touches the previous group
+        let continues = !groups.is_empty() && start <= last_end;
+        if !continues {
+            groups.push(Vec::new());
+        }
+        let group = groups.last_mut().unwrap();
+        let from = if continues {
+            last_end.max(start)
+        } else {
+            start
+        };
+
+        for (i, text) in lines.iter().enumerate().take(end).skip(from) {
+            group.push(SearchLine {
+                number: i + 1,
+                text,
+                is_match: regex.is_match(text),
+            });
+        }
+        last_end = last_end.max(end);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(groups: &[Vec<SearchLine>]) -> Vec<Vec<usize>> {
+        groups
+            .iter()
+            .map(|g| g.iter().map(|l| l.number).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_without_context() {
+        let regex = build_pattern("refused", PatternOptions::default()).unwrap();
+        let groups = search("ok\nconnection refused\nok\n", &regex, 0);
+
+        assert_eq!(numbers(&groups), vec![vec![2]]);
+        assert_eq!(groups[0][0].text, "connection refused");
+        assert!(groups[0][0].is_match);
+    }
+
+    #[test]
+    fn test_search_context_merges_groups() {
+        let text = "1\nerror\n3\n4\nerror\n6\n7\n8\n9\nerror";
+        let regex = build_pattern("error", PatternOptions::default()).unwrap();
+        let groups = search(text, &regex, 1);
+
+        assert_eq!(numbers(&groups), vec![vec![1, 2, 3, 4, 5, 6], vec![9, 10]]);
+        assert!(!groups[0][0].is_match);
+        assert!(groups[0][4].is_match);
+    }
+
+    #[test]
+    fn test_pattern_options() {
+        let insensitive = build_pattern(
+            "ERROR",
+            PatternOptions {
+                ignore_case: true,
+                ..PatternOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(insensitive.is_match("error: boom"));
+
+        let fixed = build_pattern(
+            "a.b",
+            PatternOptions {
+                fixed_strings: true,
+                ..PatternOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(fixed.is_match("a.b"));
+        assert!(!fixed.is_match("axb"));
+
+        assert!(build_pattern("(", PatternOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, TellmeError};
+
+/// Permissions of directories holding captures: owner only
+const DIR_MODE: u32 = 0o700;
+
+/// Permissions of capture files: owner read/write only
+const FILE_MODE: u32 = 0o600;
+
+/// Effective user id of this process
+fn current_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+/// The default temp directory: per user, preferring `$XDG_RUNTIME_DIR`
+///
+/// The runtime dir is private to the user already; the shared temp dir
+/// gets a per-user name so nobody else can claim it first.
+pub fn default_temp_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir).join("tellme"),
+        _ => std::env::temp_dir().join(format!("tellme-{}", current_uid())),
+    }
+}
+
+/// Create a directory only the current user can access, or secure an existing one
+///
+/// Refuses symlinks and directories owned by someone else. An existing
+/// directory of the user that others can access, e.g. one chosen with
+/// `TELLME_TEMP_DIR`, is restricted to the user.
+pub fn private_dir(path: &Path) -> Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(DIR_MODE)
+        .create(path)
+        .map_err(TellmeError::io(format!("Cannot create {}", path.display())))?;
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Err(TellmeError::UnsafePath(format!(
+            "Refusing to use {}: it is a symlink",
+            path.display()
+        )));
+    }
+    if !metadata.is_dir() {
+        return Err(TellmeError::UnsafePath(format!(
+            "Refusing to use {}: it is not a directory",
+            path.display()
+        )));
+    }
+    if metadata.uid() != current_uid() {
+        return Err(TellmeError::UnsafePath(format!(
+            "Refusing to use {}: it is owned by another user (uid {})",
+            path.display(),
+            metadata.uid()
+        )));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(DIR_MODE)).map_err(
+            TellmeError::io(format!("Cannot restrict {} to its owner", path.display())),
+        )?;
+    }
+    Ok(())
+}
+
+/// Create or truncate a file only the current user can read
+///
+/// The file is never opened through a symlink, and an existing file owned
+/// by someone else is refused.
+pub fn create_private(path: &Path) -> Result<File> {
+    open_checked(path, true)
+}
+
+/// Open a file only the current user can read, creating it if needed
+///
+/// Same checks as [`create_private`], but existing contents are kept.
+pub fn open_private(path: &Path) -> Result<File> {
+    open_checked(path, false)
+}
+
+/// Open a file for writing without following symlinks, and check its owner
+fn open_checked(path: &Path, truncate: bool) -> Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(truncate)
+        .mode(FILE_MODE)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(|e| match e.raw_os_error() {
+            Some(libc::ELOOP) => TellmeError::UnsafePath(format!(
+                "Refusing to write {}: it is a symlink",
+                path.display()
+            )),
+            _ => TellmeError::io(format!("Cannot write {}", path.display()))(e),
+        })?;
+
+    // The mode only applies to new files; fix up files from older versions
+    let metadata = file.metadata()?;
+    if metadata.uid() != current_uid() {
+        return Err(TellmeError::UnsafePath(format!(
+            "Refusing to write {}: it is owned by another user (uid {})",
+            path.display(),
+            metadata.uid()
+        )));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        file.set_permissions(fs::Permissions::from_mode(FILE_MODE))?;
+    }
+    Ok(file)
+}
+
+/// Write a whole file only the current user can read
+pub fn write_private(path: &Path, data: impl AsRef<[u8]>) -> Result<()> {
+    create_private(path)?.write_all(data.as_ref())?;
+    Ok(())
+}
+
+/// Replace a file in one step, so readers see either the old or the new contents
+///
+/// The data is written to `<name>.<pid>-<random>.tmp` next to the file, then
+/// renamed over it, so concurrent writers never share a temp file. Writers
+/// that read the file before replacing it must still hold a lock.
+pub fn write_atomic(path: &Path, data: impl AsRef<[u8]>) -> Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        uuid::Uuid::new_v4().simple()
+    ));
+    let tmp = path.with_file_name(name);
+
+    let written = create_private(&tmp).and_then(|mut file| {
+        file.write_all(data.as_ref())?;
+        file.sync_data()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    });
+    if written.is_err() {
+        fs::remove_file(&tmp).ok();
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().mode() & 0o777
+    }
+
+    #[test]
+    fn test_private_dir() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("a").join("tellme");
+
+        private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+
+        // An existing directory of the user is restricted to them
+        for open in [0o750, 0o777] {
+            fs::set_permissions(&dir, fs::Permissions::from_mode(open)).unwrap();
+            private_dir(&dir).unwrap();
+            assert_eq!(mode(&dir), 0o700);
+        }
+
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        let error = private_dir(&link).unwrap_err().to_string();
+        assert!(error.contains("symlink"), "{}", error);
+    }
+
+    #[test]
+    fn test_write_private() {
+        let temp = tempdir().unwrap();
+        let file = temp.path().join("output");
+
+        write_private(&file, "secret").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "secret");
+        assert_eq!(mode(&file), 0o600);
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&file, "again").unwrap();
+        assert_eq!(mode(&file), 0o600);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let temp = tempdir().unwrap();
+        let file = temp.path().join("meta");
+        fs::write(&file, "old").unwrap();
+
+        // A reader holding the old file keeps its contents
+        let old = File::open(&file).unwrap();
+        write_atomic(&file, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(std::io::read_to_string(old).unwrap(), "old");
+        assert_eq!(mode(&file), 0o600);
+        // No temp file is left behind
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_write_atomic() {
+        let temp = tempdir().unwrap();
+        let file = temp.path().join("index.json");
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let file = &file;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        write_atomic(file, format!("writer {}", i)).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(fs::read_to_string(&file).unwrap().starts_with("writer "));
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_refuses_symlinked_file() {
+        let temp = tempdir().unwrap();
+        let target = temp.path().join("target");
+        fs::write(&target, "keep me").unwrap();
+        let link = temp.path().join("output");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let error = write_private(&link, "overwritten").unwrap_err().to_string();
+        assert!(error.contains("symlink"), "{}", error);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "keep me");
+    }
+}
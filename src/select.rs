@@ -0,0 +1,149 @@
+use regex::Regex;
+use std::str::FromStr;
+
+use crate::search;
+
+/// A 1-based, inclusive range of lines, written `a:b`; either end may be left out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    /// First line, or the start of the output
+    pub start: Option<usize>,
+
+    /// Last line, or the end of the output
+    pub end: Option<usize>,
+}
+
+impl FromStr for LineRange {
+    type Err = String;
+
+    /// Parse `100:200`, `100:`, `:200` or a single line `150`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let number = |part: &str| -> Result<Option<usize>, String> {
+            match part.trim() {
+                "" => Ok(None),
+                part => match part.parse::<usize>() {
+                    Ok(0) | Err(_) => Err(format!("invalid line number '{}'", part)),
+                    Ok(n) => Ok(Some(n)),
+                },
+            }
+        };
+
+        let (start, end) = match text.split_once(':') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => {
+                let line = number(text)?.ok_or("expected a line range like 100:200")?;
+                (Some(line), Some(line))
+            }
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if start > end => {
+                Err(format!("range {}:{} ends before it starts", start, end))
+            }
+            _ => Ok(Self { start, end }),
+        }
+    }
+}
+
+/// Which lines of a capture's output to keep
+#[derive(Debug, Clone, Default)]
+pub enum Selection {
+    /// The whole output
+    #[default]
+    All,
+
+    /// A range of lines
+    Lines(LineRange),
+
+    /// The first lines
+    Head(usize),
+
+    /// The last lines
+    Tail(usize),
+
+    /// Matching lines with some context, groups separated by `--`
+    Grep { regex: Regex, context: usize },
+}
+
+impl Selection {
+    /// Keep the selected lines of `text`
+    pub fn apply(&self, text: &str) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        let kept: &[&str] = match self {
+            Selection::All => return text.to_string(),
+            Selection::Lines(range) => {
+                let start = range.start.unwrap_or(1).min(lines.len() + 1) - 1;
+                let end = range.end.unwrap_or(lines.len()).clamp(start, lines.len());
+                &lines[start..end]
+            }
+            Selection::Head(count) => &lines[..(*count).min(lines.len())],
+            Selection::Tail(count) => &lines[lines.len().saturating_sub(*count)..],
+            Selection::Grep { regex, context } => {
+                let groups: Vec<String> = search::search(text, regex, *context)
+                    .iter()
+                    .map(|group| {
+                        group
+                            .iter()
+                            .map(|line| line.text)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    })
+                    .collect();
+                return groups.join("\n--\n");
+            }
+        };
+        kept.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    #[test]
+    fn test_parse_line_range() {
+        let range = |start, end| LineRange { start, end };
+        assert_eq!("2:4".parse(), Ok(range(Some(2), Some(4))));
+        assert_eq!("2:".parse(), Ok(range(Some(2), None)));
+        assert_eq!(":4".parse(), Ok(range(None, Some(4))));
+        assert_eq!("3".parse(), Ok(range(Some(3), Some(3))));
+
+        assert!("4:2".parse::<LineRange>().is_err());
+        assert!("0:2".parse::<LineRange>().is_err());
+        assert!("a:b".parse::<LineRange>().is_err());
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines = |text: &str| Selection::Lines(text.parse().unwrap()).apply(TEXT);
+        assert_eq!(lines("2:3"), "two\nthree");
+        assert_eq!(lines(":2"), "one\ntwo");
+        assert_eq!(lines("4:"), "four\nfive");
+        assert_eq!(lines("4:100"), "four\nfive");
+        assert_eq!(lines("9:10"), "");
+    }
+
+    #[test]
+    fn test_head_and_tail() {
+        assert_eq!(Selection::Head(2).apply(TEXT), "one\ntwo");
+        assert_eq!(Selection::Tail(2).apply(TEXT), "four\nfive");
+        assert_eq!(Selection::Tail(10).apply(TEXT), TEXT.trim_end());
+        assert_eq!(Selection::All.apply(TEXT), TEXT);
+    }
+
+    #[test]
+    fn test_grep() {
+        let grep = |pattern: &str, context| {
+            Selection::Grep {
+                regex: Regex::new(pattern).unwrap(),
+                context,
+            }
+            .apply(TEXT)
+        };
+        assert_eq!(grep("^t", 0), "two\nthree");
+        assert_eq!(grep("one|five", 0), "one\n--\nfive");
+        assert_eq!(grep("three", 1), "two\nthree\nfour");
+        assert_eq!(grep("six", 1), "");
+    }
+}
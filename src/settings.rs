@@ -0,0 +1,505 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compress::Compression;
+use crate::error::{Result, TellmeError};
+use crate::summary::Severity;
+
+/// User settings, loaded from `config.toml` in the configuration directory
+///
+/// Every field has a default, so a missing file or a partial file is valid.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Capture storage settings
+    pub capture: CaptureSettings,
+
+    /// Persistent archive settings
+    pub archive: ArchiveSettings,
+
+    /// Diagnostic extraction settings for `--summary`
+    pub summary: SummarySettings,
+
+    /// Where `--clipboard` copies to
+    pub clipboard: ClipboardSettings,
+
+    /// Paste service used by `tellme share`
+    pub share: ShareSettings,
+
+    /// Programs run when captures finish or are saved
+    pub hooks: HookSettings,
+
+    /// Desktop notifications when long commands finish
+    pub notify: NotifySettings,
+}
+
+/// Settings for how captures are stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Compression applied to completed captures
+    pub compression: Compression,
+
+    /// Completed captures kept per shell when the archive is disabled
+    pub history: usize,
+
+    /// Environment variables recorded with each capture, for `tellme rerun --env`
+    pub env: Vec<String>,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            compression: Compression::None,
+            history: 20,
+            env: Vec::new(),
+        }
+    }
+}
+
+/// Settings for the persistent capture archive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveSettings {
+    /// Whether completed captures are archived
+    pub enabled: bool,
+
+    /// Archive directory (default: <data dir>/tellme/archive)
+    pub dir: Option<PathBuf>,
+
+    /// Remove captures older than this many days (0 = keep forever)
+    pub max_age_days: u64,
+
+    /// Keep at most this many captures (0 = unlimited)
+    pub max_count: usize,
+
+    /// Keep the archive below this many megabytes on disk (0 = unlimited)
+    pub max_size_mb: u64,
+}
+
+impl Default for ArchiveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_age_days: 30,
+            max_count: 1000,
+            max_size_mb: 500,
+        }
+    }
+}
+
+/// Settings for extracting diagnostics with `--summary`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummarySettings {
+    /// Extra line patterns, for tools without a built-in extractor
+    pub patterns: Vec<PatternSettings>,
+}
+
+/// Settings for copying output with `--clipboard`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardSettings {
+    /// Backend to copy with (default: detected from the environment)
+    pub backend: ClipboardBackend,
+
+    /// File written by the `file` backend (default: <data dir>/tellme/clipboard.txt)
+    pub file: Option<PathBuf>,
+
+    /// Warn when copying more than this many kilobytes (0 = never)
+    pub max_size_kb: u64,
+
+    /// Copy only this many last lines when over `max_size_kb` and no lines
+    /// were selected (0 = copy everything)
+    pub auto_tail: usize,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        Self {
+            backend: ClipboardBackend::Auto,
+            file: None,
+            max_size_kb: 256,
+            auto_tail: 0,
+        }
+    }
+}
+
+/// A way of copying text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardBackend {
+    /// Try the backends that suit the environment, in order
+    #[default]
+    Auto,
+
+    /// The system clipboard, through the display server
+    System,
+
+    /// OSC 52 escape sequences, copied by the terminal (also over SSH)
+    Osc52,
+
+    /// The `wl-copy` command (Wayland)
+    WlCopy,
+
+    /// The `xclip` command (X11)
+    Xclip,
+
+    /// The `xsel` command (X11)
+    Xsel,
+
+    /// The `pbcopy` command (macOS)
+    Pbcopy,
+
+    /// A plain file, when no clipboard is reachable
+    File,
+}
+
+/// Settings for uploading captures with `tellme share`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareSettings {
+    /// Endpoint the capture is sent to
+    pub url: Option<String>,
+
+    /// HTTP method of the upload
+    pub method: String,
+
+    /// Extra request headers, e.g. for authentication; `${VAR}` is replaced
+    /// by the environment variable, so tokens can stay out of the file
+    pub headers: BTreeMap<String, String>,
+
+    /// How the capture is put in the request body
+    pub body: ShareBody,
+
+    /// Form field holding the capture, for the `form` body
+    pub field: String,
+
+    /// JSON body for the `json` body, with `{{content}}`, `{{output}}` and
+    /// `{{command}}` replaced by JSON strings
+    pub template: Option<String>,
+
+    /// JSON pointer to the URL in a JSON response (default: the whole response)
+    pub response: Option<String>,
+
+    /// Extra regexes whose matches are redacted before uploading
+    pub redact: Vec<String>,
+
+    /// Give up on the upload after this many seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for ShareSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            method: "POST".to_string(),
+            headers: BTreeMap::new(),
+            body: ShareBody::Form,
+            field: "content".to_string(),
+            template: None,
+            response: None,
+            redact: Vec::new(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Request body of a paste upload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareBody {
+    /// `multipart/form-data` with the capture in one field
+    #[default]
+    Form,
+
+    /// A JSON document built from `template`
+    Json,
+
+    /// The capture as plain text
+    Raw,
+}
+
+/// Programs run in the background after captures and saves
+///
+/// Each hook is a `sh -c` command line, given the capture as `TELLME_*`
+/// environment variables and as JSON on stdin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookSettings {
+    /// Run after every captured command finishes
+    pub on_capture_complete: Option<String>,
+
+    /// Run after a captured command exits with a non-zero code
+    pub on_failure: Option<String>,
+
+    /// Run after a capture is saved to a file
+    pub on_save: Option<String>,
+
+    /// Kill a hook still running after this many seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for HookSettings {
+    fn default() -> Self {
+        Self {
+            on_capture_complete: None,
+            on_failure: None,
+            on_save: None,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl HookSettings {
+    /// The command configured for a hook, if any
+    pub fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::OnCaptureComplete => self.on_capture_complete.as_deref(),
+            Hook::OnFailure => self.on_failure.as_deref(),
+            Hook::OnSave => self.on_save.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// Events hooks can run on, named like their settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum Hook {
+    /// A captured command finished
+    OnCaptureComplete,
+
+    /// A captured command failed
+    OnFailure,
+
+    /// A capture was saved to a file
+    OnSave,
+}
+
+impl Hook {
+    /// Name of the hook in the settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::OnCaptureComplete => "on_capture_complete",
+            Hook::OnFailure => "on_failure",
+            Hook::OnSave => "on_save",
+        }
+    }
+}
+
+/// Settings for desktop notifications about finished commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifySettings {
+    /// Whether to notify at all
+    pub enabled: bool,
+
+    /// Only notify about commands that ran at least this long
+    pub min_duration_secs: u64,
+
+    /// How notifications are shown
+    pub backend: NotifyBackend,
+}
+
+impl Default for NotifySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_duration_secs: 60,
+            backend: NotifyBackend::Auto,
+        }
+    }
+}
+
+/// How desktop notifications are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyBackend {
+    /// D-Bus, then `notify-send`
+    #[default]
+    Auto,
+
+    /// The freedesktop notification service on the session D-Bus
+    Dbus,
+
+    /// The `notify-send` command
+    NotifySend,
+}
+
+/// A line pattern reporting one diagnostic per matching line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternSettings {
+    /// Name of the tool, shown with its diagnostics
+    pub name: String,
+
+    /// Regex with a `message` group and optional `file`, `line`, `column`
+    /// and `severity` groups
+    pub regex: String,
+
+    /// Severity when the pattern has no `severity` group
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl Settings {
+    /// Name of the settings file inside the configuration directory
+    pub const FILE_NAME: &'static str = "config.toml";
+
+    /// Load settings from the configuration directory, or return defaults
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(Self::FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(|e| {
+                TellmeError::InvalidConfig(format!("Invalid config file {}: {}", path.display(), e))
+            }),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_default() {
+        let dir = tempdir().unwrap();
+        let settings = Settings::load(dir.path()).unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.capture.compression, Compression::None);
+    }
+
+    #[test]
+    fn test_load_compression() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[capture]\ncompression = \"zstd\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+        assert_eq!(settings.capture.compression, Compression::Zstd);
+    }
+
+    #[test]
+    fn test_load_archive() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[archive]\nenabled = true\nmax_count = 10\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+        assert!(settings.archive.enabled);
+        assert_eq!(settings.archive.max_count, 10);
+        // Unset fields keep their defaults
+        assert_eq!(settings.archive.max_age_days, 30);
+    }
+
+    #[test]
+    fn test_load_summary_patterns() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[[summary.patterns]]\nname = \"lint\"\nregex = \"^LINT (?P<message>.+)$\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+        assert_eq!(settings.summary.patterns.len(), 1);
+        assert_eq!(settings.summary.patterns[0].name, "lint");
+        assert_eq!(settings.summary.patterns[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_load_clipboard() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[clipboard]\nbackend = \"wl-copy\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+        assert_eq!(settings.clipboard.backend, ClipboardBackend::WlCopy);
+        assert_eq!(settings.clipboard.file, None);
+    }
+
+    #[test]
+    fn test_load_share() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[share]\nurl = \"https://paste.example.com\"\nbody = \"json\"\n\
+             headers = { Authorization = \"Bearer ${PASTE_TOKEN}\" }\n",
+        )
+        .unwrap();
+
+        let settings = Settings::load(dir.path()).unwrap();
+        assert_eq!(
+            settings.share.url.as_deref(),
+            Some("https://paste.example.com")
+        );
+        assert_eq!(settings.share.body, ShareBody::Json);
+        assert_eq!(
+            settings.share.headers["Authorization"],
+            "Bearer ${PASTE_TOKEN}"
+        );
+        assert_eq!(settings.share.method, "POST");
+    }
+
+    #[test]
+    fn test_load_hooks() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[hooks]\non_failure = \"notify-send failed\"\non_save = \"\"\n",
+        )
+        .unwrap();
+
+        let hooks = Settings::load(dir.path()).unwrap().hooks;
+        assert_eq!(hooks.command(Hook::OnFailure), Some("notify-send failed"));
+        assert_eq!(hooks.command(Hook::OnCaptureComplete), None);
+        // An empty command turns the hook off
+        assert_eq!(hooks.command(Hook::OnSave), None);
+        assert_eq!(hooks.timeout_secs, 10);
+    }
+
+    #[test]
+    fn test_load_notify() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[notify]\nenabled = true\nbackend = \"notify-send\"\n",
+        )
+        .unwrap();
+
+        let notify = Settings::load(dir.path()).unwrap().notify;
+        assert!(notify.enabled);
+        assert_eq!(notify.backend, NotifyBackend::NotifySend);
+        assert_eq!(notify.min_duration_secs, 60);
+        assert!(!Settings::default().notify.enabled);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join(Settings::FILE_NAME),
+            "[capture]\ncompression = \"lzma\"\n",
+        )
+        .unwrap();
+
+        assert!(Settings::load(dir.path()).is_err());
+    }
+}
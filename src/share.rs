@@ -0,0 +1,413 @@
+use regex::Regex;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+
+use crate::error::{Result, TellmeError};
+use crate::export::{self, Log};
+use crate::secure;
+use crate::settings::{ShareBody, ShareSettings};
+
+/// `${VAR}` references in header values
+static ENV_VAR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// `{{content}}`, `{{output}}` and `{{command}}` in a JSON template
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{(content|output|command)\}\}").unwrap());
+
+/// How much of an error response is shown
+const ERROR_SNIPPET_CHARS: usize = 200;
+
+/// A capture ready to upload, with secrets already redacted
+pub struct Paste<'a> {
+    /// The command line
+    pub command: &'a str,
+
+    /// The output, without ANSI escapes
+    pub output: &'a str,
+}
+
+impl Paste<'_> {
+    /// The capture as a text log with its command header
+    pub fn content(&self) -> String {
+        let mut content = Vec::new();
+        export::write_log(&mut content, &Log::new(self.command, self.output))
+            .expect("writing to memory cannot fail");
+        String::from_utf8_lossy(&content).into_owned()
+    }
+}
+
+/// Replace `${VAR}` in `text` with the value from `lookup`
+fn expand_env(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut missing = None;
+    let expanded = ENV_VAR.replace_all(text, |caps: &regex::Captures| {
+        lookup(&caps[1]).unwrap_or_else(|| {
+            missing.get_or_insert_with(|| caps[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(name) => Err(TellmeError::InvalidConfig(format!(
+            "Environment variable {} used in the [share] headers is not set",
+            name
+        ))),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// Fill the placeholders of a JSON template with JSON strings
+///
+/// Done in one pass, so placeholders inside the captured text stay as they are.
+fn fill_template(template: &str, paste: &Paste, content: &str) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &regex::Captures| {
+            let value = match &caps[1] {
+                "content" => content,
+                "output" => paste.output,
+                _ => paste.command,
+            };
+            serde_json::Value::from(value).to_string()
+        })
+        .into_owned()
+}
+
+/// Escape a form field name for a `Content-Disposition` header, as browsers do
+fn form_quote(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Build the content type and body of the upload
+fn request_body(settings: &ShareSettings, paste: &Paste) -> Result<(String, Vec<u8>)> {
+    let content = paste.content();
+    match settings.body {
+        ShareBody::Form => {
+            let boundary = format!("tellme-{}", uuid::Uuid::new_v4().simple());
+            let mut body = format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"tellme.log\"\r\n\
+                 Content-Type: text/plain; charset=utf-8\r\n\r\n",
+                boundary,
+                form_quote(&settings.field)
+            )
+            .into_bytes();
+            body.extend_from_slice(content.as_bytes());
+            body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+            Ok((format!("multipart/form-data; boundary={}", boundary), body))
+        }
+        ShareBody::Json => {
+            let body = match &settings.template {
+                Some(template) => {
+                    let body = fill_template(template, paste, &content);
+                    serde_json::from_str::<serde_json::Value>(&body).map_err(|e| {
+                        TellmeError::InvalidConfig(format!(
+                            "The [share] template is not valid JSON: {}",
+                            e
+                        ))
+                    })?;
+                    body
+                }
+                None => serde_json::json!({ &settings.field: content }).to_string(),
+            };
+            Ok(("application/json".to_string(), body.into_bytes()))
+        }
+        ShareBody::Raw => Ok((
+            "text/plain; charset=utf-8".to_string(),
+            content.into_bytes(),
+        )),
+    }
+}
+
+/// Quote a value for a curl config file
+fn curl_quote(value: &str) -> Result<String> {
+    if value.contains(['\n', '\r']) {
+        return Err(TellmeError::InvalidConfig(format!(
+            "Line breaks are not allowed in the [share] settings: {:?}",
+            value
+        )));
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+/// The curl config holding the URL and headers, which may carry tokens
+fn curl_config(
+    settings: &ShareSettings,
+    url: &str,
+    content_type: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let mut config = format!("url = {}\n", curl_quote(url)?);
+    config.push_str(&format!(
+        "header = {}\n",
+        curl_quote(&format!("Content-Type: {}", content_type))?
+    ));
+    // curl would otherwise wait for a `100 Continue` on large bodies
+    config.push_str("header = \"Expect:\"\n");
+    for (name, value) in &settings.headers {
+        let value = expand_env(value, &lookup)?;
+        config.push_str(&format!(
+            "header = {}\n",
+            curl_quote(&format!("{}: {}", name, value))?
+        ));
+    }
+    Ok(config)
+}
+
+/// Find the paste URL in curl's output: the body, then the HTTP status
+fn parse_response(stdout: &str, pointer: Option<&str>) -> Result<String> {
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", stdout));
+    let status: u16 = status.trim().parse().map_err(|_| {
+        TellmeError::UploadFailed(format!("unexpected reply from curl: {:?}", status))
+    })?;
+    if !(200..300).contains(&status) {
+        let snippet: String = body.trim().chars().take(ERROR_SNIPPET_CHARS).collect();
+        return Err(TellmeError::UploadFailed(if snippet.is_empty() {
+            format!("the server answered {}", status)
+        } else {
+            format!("the server answered {}: {}", status, snippet)
+        }));
+    }
+
+    let url = match pointer {
+        Some(pointer) => {
+            let json: serde_json::Value = serde_json::from_str(body).map_err(|e| {
+                TellmeError::UploadFailed(format!("the response is not JSON: {}", e))
+            })?;
+            json.pointer(pointer)
+                .and_then(|value| value.as_str())
+                .ok_or_else(|| {
+                    TellmeError::UploadFailed(format!("the response has no string at {}", pointer))
+                })?
+                .trim()
+                .to_string()
+        }
+        None => body.trim().to_string(),
+    };
+    if url.is_empty() {
+        return Err(TellmeError::UploadFailed(
+            "the response does not contain a URL".to_string(),
+        ));
+    }
+    Ok(url)
+}
+
+/// Upload a capture with curl and return the URL of the paste
+///
+/// The URL and headers go through `config_file`, a private file removed
+/// however the upload ends, so tokens never show up in the process list.
+pub fn upload(settings: &ShareSettings, paste: &Paste, config_file: &Path) -> Result<String> {
+    let url = settings.url.as_deref().ok_or_else(|| {
+        TellmeError::InvalidConfig(
+            "No paste service configured; set url in the [share] section of config.toml"
+                .to_string(),
+        )
+    })?;
+    let (content_type, body) = request_body(settings, paste)?;
+    let curl_config = curl_config(settings, url, &content_type, |name| {
+        std::env::var(name).ok()
+    })?;
+
+    if let Some(parent) = config_file.parent() {
+        secure::private_dir(parent)?;
+    }
+    let _remove = RemoveOnDrop(config_file);
+    secure::write_private(config_file, curl_config)?;
+    let response = run_curl(settings, config_file, &body)?;
+
+    parse_response(&response, settings.response.as_deref())
+}
+
+/// Removes a file when dropped, on every way out of a function
+struct RemoveOnDrop<'a>(&'a Path);
+
+impl Drop for RemoveOnDrop<'_> {
+    fn drop(&mut self) {
+        std::fs::remove_file(self.0).ok();
+    }
+}
+
+/// Send `body` with curl, returning the response body and status
+fn run_curl(settings: &ShareSettings, config_file: &Path, body: &[u8]) -> Result<String> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--location"])
+        .arg("--max-time")
+        .arg(settings.timeout_secs.to_string())
+        .arg("--request")
+        .arg(&settings.method)
+        .arg("--config")
+        .arg(config_file)
+        .args(["--data-binary", "@-", "--write-out", "\n%{http_code}"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| TellmeError::UploadFailed(format!("cannot run curl: {}", e)))?;
+
+    // curl reads the whole body before it sends anything
+    let written = child
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(body));
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(TellmeError::UploadFailed(
+            stderr.trim().trim_start_matches("curl: ").to_string(),
+        ));
+    }
+    written.map_err(|e| TellmeError::UploadFailed(format!("curl did not read the body: {}", e)))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASTE: Paste = Paste {
+        command: "make",
+        output: "error: \"boom\"",
+    };
+
+    fn settings(body: ShareBody) -> ShareSettings {
+        ShareSettings {
+            url: Some("https://paste.example.com".to_string()),
+            body,
+            ..ShareSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| (name == "TOKEN").then(|| "s3cret".to_string());
+        assert_eq!(
+            expand_env("Bearer ${TOKEN}", lookup).unwrap(),
+            "Bearer s3cret"
+        );
+        assert_eq!(expand_env("plain $TOKEN", lookup).unwrap(), "plain $TOKEN");
+
+        let error = expand_env("${MISSING}", lookup).unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+        assert!(error.to_string().contains("MISSING"));
+    }
+
+    #[test]
+    fn test_form_body() {
+        let (content_type, body) = request_body(&settings(ShareBody::Form), &PASTE).unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        assert!(body.contains("name=\"content\""));
+        assert!(body.contains(&PASTE.content()));
+        assert!(body.ends_with(&format!("\r\n--{}--\r\n", boundary)));
+    }
+
+    #[test]
+    fn test_json_body() {
+        let (content_type, body) = request_body(&settings(ShareBody::Json), &PASTE).unwrap();
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["content"], PASTE.content());
+
+        let mut templated = settings(ShareBody::Json);
+        templated.template =
+            Some(r#"{"files": {"log": {"text": {{output}}}}, "title": {{command}}}"#.to_string());
+        let (_, body) = request_body(&templated, &PASTE).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["files"]["log"]["text"], PASTE.output);
+        assert_eq!(json["title"], "make");
+
+        templated.template = Some("{\"text\": \"{{output}}\"}".to_string());
+        let error = request_body(&templated, &PASTE).unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+    }
+
+    #[test]
+    fn test_template_placeholders_in_capture() {
+        let paste = Paste {
+            command: "echo {{output}}",
+            output: "{{command}} \"{{content}}\"",
+        };
+        let mut templated = settings(ShareBody::Json);
+        templated.template = Some(r#"{"title": {{command}}, "text": {{output}}}"#.to_string());
+
+        let (_, body) = request_body(&templated, &paste).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["title"], paste.command);
+        assert_eq!(json["text"], paste.output);
+    }
+
+    #[test]
+    fn test_form_field_name_escaped() {
+        let mut form = settings(ShareBody::Form);
+        form.field = "a\"b\r\nX-Injected: 1".to_string();
+        let (_, body) = request_body(&form, &PASTE).unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("name=\"a%22b%0D%0AX-Injected: 1\""));
+    }
+
+    #[test]
+    fn test_curl_config() {
+        let mut settings = settings(ShareBody::Raw);
+        settings
+            .headers
+            .insert("Authorization".to_string(), "Bearer ${TOKEN}".to_string());
+        let config = curl_config(&settings, "https://x/\"a\"", "text/plain", |_| {
+            Some("t\"ok".to_string())
+        })
+        .unwrap();
+        assert_eq!(
+            config,
+            "url = \"https://x/\\\"a\\\"\"\n\
+             header = \"Content-Type: text/plain\"\n\
+             header = \"Expect:\"\n\
+             header = \"Authorization: Bearer t\\\"ok\"\n"
+        );
+
+        let error = curl_config(&settings, "https://x", "text/plain", |_| {
+            Some("a\nb".to_string())
+        })
+        .unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response("https://paste.example.com/abc\n\n200", None).unwrap(),
+            "https://paste.example.com/abc"
+        );
+        assert_eq!(
+            parse_response(
+                "{\"data\": {\"url\": \"https://p/1\"}}\n201",
+                Some("/data/url")
+            )
+            .unwrap(),
+            "https://p/1"
+        );
+
+        let error = parse_response("quota exceeded\n429", None).unwrap_err();
+        assert_eq!(error.exit_code(), 9);
+        assert!(error.to_string().contains("429: quota exceeded"));
+
+        assert!(parse_response("{}\n200", Some("/url")).is_err());
+        assert!(parse_response("\n200", None).is_err());
+    }
+
+    #[test]
+    fn test_missing_url() {
+        let error = upload(
+            &ShareSettings::default(),
+            &PASTE,
+            Path::new("/nonexistent/share"),
+        )
+        .unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+    }
+}
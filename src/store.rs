@@ -0,0 +1,610 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive::Archive;
+use crate::compress;
+use crate::config::Config;
+use crate::error::{Result, TellmeError};
+use crate::gc;
+use crate::lock::FileLock;
+use crate::meta::CaptureMeta;
+use crate::secure;
+
+/// Where a capture is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureSource {
+    /// The current capture of a shell, in the temp dir
+    Live,
+
+    /// A completed capture kept for the lifetime of its shell
+    History,
+
+    /// A completed capture in the persistent archive
+    Archive,
+}
+
+/// Files backing a capture
+#[derive(Debug, Clone)]
+enum Location {
+    /// An entry of the archive (or shell history) rooted at this directory
+    Stored(PathBuf),
+
+    /// The current files of a shell in the temp dir
+    Live { output: PathBuf, meta: PathBuf },
+}
+
+/// A capture found in the store
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureRecord {
+    /// Capture metadata
+    #[serde(flatten)]
+    pub meta: CaptureMeta,
+
+    /// Where the capture is held
+    pub source: CaptureSource,
+
+    /// Files backing the capture
+    #[serde(skip)]
+    location: Location,
+}
+
+impl CaptureRecord {
+    /// Path of the (possibly compressed) output file
+    pub fn output_file(&self) -> PathBuf {
+        match &self.location {
+            Location::Stored(root) => Archive::new(root.clone()).output_file(&self.meta.id),
+            Location::Live { output, .. } => output.clone(),
+        }
+    }
+
+    /// Read the output of this capture, decompressing it if needed
+    pub fn read_output(&self) -> Result<Vec<u8>> {
+        let data = fs::read(self.output_file()).map_err(TellmeError::io(format!(
+            "Cannot read output of capture {}",
+            self.meta.id
+        )))?;
+        compress::decompress(data, self.meta.compression)
+    }
+
+    /// Re-read the metadata of this capture
+    ///
+    /// For a live capture this is what the shell's files hold now, which is
+    /// a newer capture once the shell has run another command. Returns `None`
+    /// when the files are gone.
+    pub fn reload_meta(&self) -> Option<CaptureMeta> {
+        match &self.location {
+            Location::Stored(_) => Some(self.meta.clone()),
+            Location::Live { meta, .. } => serde_json::from_slice(&fs::read(meta).ok()?).ok(),
+        }
+    }
+}
+
+/// A shell with capture files in the temp dir
+#[derive(Debug, Clone, Serialize)]
+pub struct ShellInfo {
+    /// Session id of the shell
+    pub session: String,
+
+    /// PID of the shell, if known
+    pub pid: Option<u32>,
+
+    /// Whether the shell process is still running
+    pub alive: bool,
+
+    /// Number of captures made by the shell
+    pub captures: usize,
+
+    /// The shell's most recent capture
+    pub last: Option<CaptureMeta>,
+}
+
+/// Criteria for selecting captures
+#[derive(Debug, Clone, Default)]
+pub struct CaptureQuery {
+    /// Only captures that exited with a non-zero code
+    pub failed: bool,
+
+    /// Only captures started at or after this time
+    pub since: Option<DateTime<Local>>,
+
+    /// Only captures run in this directory
+    pub cwd: Option<PathBuf>,
+
+    /// Only captures whose command contains this text
+    pub grep: Option<String>,
+
+    /// Only captures run by the shell with this session id
+    pub shell: Option<String>,
+}
+
+impl CaptureQuery {
+    /// Check if a capture matches every criterion
+    pub fn matches(&self, meta: &CaptureMeta) -> bool {
+        if self.failed && !meta.failed() {
+            return false;
+        }
+        if let Some(since) = self.since
+            && meta.started_at < since
+        {
+            return false;
+        }
+        if let Some(cwd) = &self.cwd
+            && !same_dir(cwd, &meta.cwd)
+        {
+            return false;
+        }
+        if let Some(pattern) = &self.grep
+            && !meta.command.contains(pattern.as_str())
+        {
+            return false;
+        }
+        if let Some(shell) = &self.shell
+            && meta.session_key() != *shell
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Compare two directories, resolving symlinks and relative paths when possible
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    canonical(a) == canonical(b)
+}
+
+/// Read-only view over every capture: live ones, shell histories and the archive
+#[derive(Debug)]
+pub struct CaptureStore<'s> {
+    /// Configuration
+    config: &'s Config,
+}
+
+impl<'s> CaptureStore<'s> {
+    /// Create a new CaptureStore
+    pub fn new(config: &'s Config) -> Self {
+        Self { config }
+    }
+
+    /// List all captures, newest first
+    ///
+    /// A capture held in several places is reported once, preferring the
+    /// archive, then the shell history, then the live files.
+    pub fn list(&self) -> Result<Vec<CaptureRecord>> {
+        let mut records = Vec::new();
+
+        let archive = Archive::new(self.config.archive_dir());
+        records.extend(Self::archived(&archive, CaptureSource::Archive)?);
+
+        if let Ok(dirs) = fs::read_dir(self.config.history_root()) {
+            for dir in dirs.flatten() {
+                let history = Archive::new(dir.path());
+                records.extend(Self::archived(&history, CaptureSource::History)?);
+            }
+        }
+
+        records.extend(self.live());
+
+        let mut seen = HashSet::new();
+        records.retain(|r| seen.insert(r.meta.id.clone()));
+        records.sort_by_key(|r| Reverse(r.meta.started_at));
+        Ok(records)
+    }
+
+    /// List the captures matching a query, newest first
+    pub fn query(&self, query: &CaptureQuery) -> Result<Vec<CaptureRecord>> {
+        let mut records = self.list()?;
+        records.retain(|r| query.matches(&r.meta));
+        Ok(records)
+    }
+
+    /// Find a capture by id or unique id prefix
+    pub fn find(&self, id: &str) -> Result<Option<CaptureRecord>> {
+        let mut matches: Vec<_> = self
+            .list()?
+            .into_iter()
+            .filter(|r| r.meta.id.starts_with(id))
+            .collect();
+
+        match matches.len() {
+            0 => Ok(None),
+            1 => Ok(matches.pop()),
+            _ => Err(TellmeError::Ambiguous {
+                kind: "Capture id",
+                key: id.to_string(),
+            }),
+        }
+    }
+
+    /// Find the most recent run of the same command before this capture
+    pub fn previous_run(&self, record: &CaptureRecord) -> Result<Option<CaptureRecord>> {
+        Ok(self.list()?.into_iter().find(|r| {
+            r.meta.id != record.meta.id
+                && r.meta.command == record.meta.command
+                && r.meta.started_at <= record.meta.started_at
+        }))
+    }
+
+    /// Delete a capture from every place that holds it
+    ///
+    /// The latest capture of a shell is in its live files and its history,
+    /// and maybe the archive; removing only the listed copy would bring it
+    /// back on the next listing. Runs under the shell's capture lock, so the
+    /// live files are not removed while the shell writes a new capture.
+    pub fn remove(&self, record: &CaptureRecord) -> Result<()> {
+        let id = &record.meta.id;
+        let session = record.meta.session_key();
+        secure::private_dir(self.config.temp_path())?;
+        let _lock = FileLock::exclusive(&self.config.shell_temp_file("lock", &session))?;
+
+        if let Location::Stored(root) = &record.location {
+            Archive::new(root.clone()).remove(id)?;
+        }
+        Archive::new(self.config.history_dir_of(&session)).remove(id)?;
+        Archive::new(self.config.archive_dir()).remove(id)?;
+
+        if self
+            .live_record(&session)
+            .is_some_and(|live| live.meta.id == *id)
+        {
+            for kind in ["cmd", "output", "meta", "done"] {
+                let file = self.config.shell_temp_file(kind, &session);
+                if file.exists() {
+                    fs::remove_file(file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pin or unpin a capture, protecting it from retention rules
+    pub fn set_pinned(&self, record: &mut CaptureRecord, pinned: bool) -> Result<()> {
+        record.meta.pinned = pinned;
+        match &record.location {
+            Location::Stored(root) => {
+                Archive::new(root.clone()).update(&record.meta)?;
+            }
+            Location::Live { meta, .. } => {
+                secure::write_atomic(meta, serde_json::to_vec(&record.meta)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records of the captures held in an archive directory
+    fn archived(archive: &Archive, source: CaptureSource) -> Result<Vec<CaptureRecord>> {
+        Ok(archive
+            .entries()?
+            .into_iter()
+            .map(|meta| CaptureRecord {
+                meta,
+                source,
+                location: Location::Stored(archive.root().to_path_buf()),
+            })
+            .collect())
+    }
+
+    /// Records of the current capture of every shell
+    fn live(&self) -> Vec<CaptureRecord> {
+        let Ok(entries) = fs::read_dir(self.config.temp_path()) else {
+            return Vec::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_prefix(".tellme_meta_")
+                    .and_then(|key| self.live_record(key))
+            })
+            .collect()
+    }
+
+    /// Every shell with capture files in the temp dir, most recently used first
+    pub fn shells(&self) -> Result<Vec<ShellInfo>> {
+        let mut sessions = BTreeSet::new();
+        if let Ok(entries) = fs::read_dir(self.config.temp_path()) {
+            sessions.extend(entries.flatten().filter_map(|e| {
+                Config::session_of_temp_file(&e.file_name().to_string_lossy()).map(str::to_string)
+            }));
+        }
+        if let Ok(dirs) = fs::read_dir(self.config.history_root()) {
+            sessions.extend(
+                dirs.flatten()
+                    .map(|d| d.file_name().to_string_lossy().to_string()),
+            );
+        }
+
+        let records = self.list()?;
+        let mut shells: Vec<ShellInfo> = sessions
+            .into_iter()
+            .map(|session| {
+                let own: Vec<_> = records
+                    .iter()
+                    .filter(|r| r.meta.session_key() == session)
+                    .collect();
+                // Sessions of shells with an older hook are named by their PID
+                let pid = gc::session_pid(self.config, &session)
+                    .or_else(|| own.first().map(|r| r.meta.shell_pid))
+                    .or_else(|| session.parse().ok());
+                let alive = match pid {
+                    Some(pid) => gc::is_shell_alive(self.config, &session, pid),
+                    None => gc::is_recently_used(self.config, &session),
+                };
+                ShellInfo {
+                    alive,
+                    session,
+                    pid,
+                    captures: own.len(),
+                    last: own.first().map(|r| r.meta.clone()),
+                }
+            })
+            .collect();
+        shells.sort_by_key(|s| Reverse(s.last.as_ref().map(|m| m.started_at)));
+        Ok(shells)
+    }
+
+    /// Find a shell by session id, unique session id prefix or PID
+    ///
+    /// Several shells can have had the same PID; the running one is preferred,
+    /// then the most recently used.
+    pub fn find_shell(&self, key: &str) -> Result<Option<ShellInfo>> {
+        let shells = self.shells()?;
+
+        if let Some(shell) = shells.iter().find(|s| s.session == key) {
+            return Ok(Some(shell.clone()));
+        }
+
+        if let Ok(pid) = key.parse::<u32>() {
+            let mut same_pid = shells.iter().filter(|s| s.pid == Some(pid));
+            if let Some(shell) = same_pid
+                .clone()
+                .find(|s| s.alive)
+                .or_else(|| same_pid.next())
+            {
+                return Ok(Some(shell.clone()));
+            }
+        }
+
+        let mut matches = shells.into_iter().filter(|s| s.session.starts_with(key));
+        match (matches.next(), matches.next()) {
+            (Some(shell), None) => Ok(Some(shell)),
+            (Some(_), Some(_)) => Err(TellmeError::Ambiguous {
+                kind: "Shell",
+                key: key.to_string(),
+            }),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// Captures whose command is still running, most recently started first
+    pub fn active(&self) -> Vec<CaptureRecord> {
+        let mut records = self.live();
+        records.retain(|r| !r.meta.is_complete());
+        records.sort_by_key(|r| Reverse(r.meta.started_at));
+        records
+    }
+
+    /// Record of the current capture of this shell
+    pub fn current(&self) -> Option<CaptureRecord> {
+        self.live_record(self.config.session())
+    }
+
+    /// Record of the current capture of the shell with this file key
+    fn live_record(&self, key: &str) -> Option<CaptureRecord> {
+        let dir = self.config.temp_path();
+        let output = dir.join(format!(".tellme_output_{}", key));
+        let meta_file = dir.join(format!(".tellme_meta_{}", key));
+        if !output.exists() {
+            return None;
+        }
+
+        // Skip metadata that is being rewritten or is from an older version
+        let meta: CaptureMeta = serde_json::from_slice(&fs::read(&meta_file).ok()?).ok()?;
+
+        Some(CaptureRecord {
+            meta,
+            source: CaptureSource::Live,
+            location: Location::Live {
+                output,
+                meta: meta_file,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureSession;
+    use crate::config::test_utils::{
+        create_test_config, create_test_config_for_shell, run_command,
+    };
+
+    #[test]
+    fn test_list_newest_first() {
+        let config = create_test_config();
+
+        run_command(&config, "cargo build", "error: boom", 101);
+        run_command(&config, "ls", "output", 0);
+
+        let records = CaptureStore::new(&config).list().unwrap();
+        let commands: Vec<_> = records.iter().map(|r| r.meta.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls", "cargo build"]);
+        assert!(records.iter().all(|r| r.source == CaptureSource::History));
+        assert_eq!(records[1].read_output().unwrap(), b"error: boom");
+    }
+
+    #[test]
+    fn test_list_includes_running_capture() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+
+        session.prepare_new_command("sleep 100").unwrap();
+
+        let store = CaptureStore::new(&config);
+        let records = store.list().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].source, CaptureSource::Live);
+        assert!(!records[0].meta.is_complete());
+        assert_eq!(store.current().unwrap().meta.id, records[0].meta.id);
+        assert_eq!(store.active().len(), 1);
+
+        session.finish_command(Some(0)).unwrap();
+        assert!(store.active().is_empty());
+        assert!(records[0].reload_meta().unwrap().is_complete());
+    }
+
+    #[test]
+    fn test_query() {
+        let config = create_test_config();
+
+        run_command(&config, "cargo build", "output", 101);
+        run_command(&config, "cargo fmt", "output", 0);
+        run_command(&config, "make", "output", 2);
+
+        let store = CaptureStore::new(&config);
+        let failed = store
+            .query(&CaptureQuery {
+                failed: true,
+                grep: Some("cargo".to_string()),
+                ..CaptureQuery::default()
+            })
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].meta.command, "cargo build");
+
+        let future = store
+            .query(&CaptureQuery {
+                since: Some(Local::now() + chrono::Duration::hours(1)),
+                ..CaptureQuery::default()
+            })
+            .unwrap();
+        assert!(future.is_empty());
+
+        let here = store
+            .query(&CaptureQuery {
+                cwd: Some(std::env::current_dir().unwrap()),
+                ..CaptureQuery::default()
+            })
+            .unwrap();
+        assert_eq!(here.len(), 3);
+
+        let other_shell = store
+            .query(&CaptureQuery {
+                shell: Some("another-session".to_string()),
+                ..CaptureQuery::default()
+            })
+            .unwrap();
+        assert!(other_shell.is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let config = create_test_config();
+        let session = CaptureSession::new(&config);
+
+        run_command(&config, "make", "output", 0);
+        let id = session.read_meta().unwrap().unwrap().id;
+
+        let store = CaptureStore::new(&config);
+        assert_eq!(store.find(&id[..4]).unwrap().unwrap().meta.command, "make");
+        assert!(store.find("not-an-id").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_previous_run() {
+        let config = create_test_config();
+
+        run_command(&config, "make", "output", 2);
+        run_command(&config, "ls", "output", 0);
+        run_command(&config, "make", "output", 0);
+
+        let store = CaptureStore::new(&config);
+        let latest = store.current().unwrap();
+        let previous = store.previous_run(&latest).unwrap().unwrap();
+        assert_eq!(previous.meta.command, "make");
+        assert_eq!(previous.meta.exit_code, Some(2));
+        assert!(store.previous_run(&previous).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_shells() {
+        let config = create_test_config();
+        let other = create_test_config_for_shell(&config, std::process::id());
+
+        run_command(&config, "make", "output", 0);
+        run_command(&config, "ls", "output", 0);
+        run_command(&other, "cargo test", "output", 0);
+
+        let shells = CaptureStore::new(&config).shells().unwrap();
+        assert_eq!(shells.len(), 2);
+
+        assert_eq!(shells[0].session, std::process::id().to_string());
+        assert_eq!(shells[0].pid, Some(std::process::id()));
+        assert!(shells[0].alive);
+        assert_eq!(shells[0].captures, 1);
+
+        assert_eq!(shells[1].pid, Some(config.pid()));
+        assert_eq!(shells[1].captures, 2);
+        assert_eq!(shells[1].last.as_ref().unwrap().command, "ls");
+    }
+
+    #[test]
+    fn test_find_shell() {
+        let config = create_test_config();
+        let mut first = create_test_config_for_shell(&config, 4242);
+        first.attach("3f2a9c10-aaaa", 4242);
+        let mut second = first.clone();
+        second.attach("3f2a9c10-bbbb", 4242);
+
+        run_command(&first, "make", "output", 0);
+        run_command(&second, "ls", "output", 0);
+
+        let store = CaptureStore::new(&config);
+        let find = |key: &str| store.find_shell(key).unwrap().map(|s| s.session);
+
+        assert_eq!(find("3f2a9c10-aaaa").as_deref(), Some("3f2a9c10-aaaa"));
+        assert_eq!(find("3f2a9c10-b").as_deref(), Some("3f2a9c10-bbbb"));
+        assert!(store.find_shell("3f2a9c10").is_err());
+
+        // By PID, the most recently used shell wins when none is running
+        assert_eq!(find("4242").as_deref(), Some("3f2a9c10-bbbb"));
+        assert!(find("no-such-shell").is_none());
+    }
+
+    #[test]
+    fn test_remove_and_pin() {
+        let config = create_test_config();
+
+        run_command(&config, "make", "output", 0);
+        run_command(&config, "ls", "output", 0);
+
+        let store = CaptureStore::new(&config);
+        let mut records = store.list().unwrap();
+
+        store.set_pinned(&mut records[1], true).unwrap();
+        assert!(
+            store
+                .find(&records[1].meta.id)
+                .unwrap()
+                .unwrap()
+                .meta
+                .pinned
+        );
+
+        // Removing the history entry of "ls" removes the shell's current files too
+        store.remove(&records[0]).unwrap();
+        assert!(store.find(&records[0].meta.id).unwrap().is_none());
+        assert!(!config.output_file().exists());
+        assert!(!config.done_file().exists());
+
+        // Older captures of the shell are kept
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,639 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, TellmeError};
+use crate::settings::{PatternSettings, SummarySettings};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
+impl Severity {
+    /// The severity word, as used in compiler messages
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    /// Parse a severity word as printed by compilers (`error`, `fatal error`, `warning`)
+    fn parse(text: &str) -> Self {
+        if text.to_lowercase().contains("warn") {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+}
+
+/// An error or warning found in a command's output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Name of the extractor that found it
+    pub tool: String,
+
+    /// Error or warning
+    pub severity: Severity,
+
+    /// One-line description
+    pub message: String,
+
+    /// Source file the diagnostic points to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    /// 1-based line in `file`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+
+    /// 1-based column in `file`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+
+    /// 1-based line of the output where the diagnostic starts
+    pub output_line: usize,
+
+    /// The full diagnostic as printed, possibly several lines
+    pub text: String,
+}
+
+impl Diagnostic {
+    fn new(tool: &str, severity: Severity, message: &str, output_line: usize) -> Self {
+        Self {
+            tool: tool.to_string(),
+            severity,
+            message: message.trim().to_string(),
+            file: None,
+            line: None,
+            column: None,
+            output_line,
+            text: String::new(),
+        }
+    }
+
+    /// Set the location from `file`, `line` and `column` regex captures
+    fn locate(mut self, caps: &Captures) -> Self {
+        self.file = caps.name("file").map(|m| m.as_str().to_string());
+        self.line = caps.name("line").and_then(|m| m.as_str().parse().ok());
+        self.column = caps.name("column").and_then(|m| m.as_str().parse().ok());
+        self
+    }
+
+    fn with_text(mut self, lines: &[&str]) -> Self {
+        self.text = lines.join("\n").trim_end().to_string();
+        self
+    }
+}
+
+/// Finds diagnostics of one tool in a command's output
+///
+/// Implement this to teach `tellme --summary` a new output format, then
+/// register it with [`Summarizer::register`].
+pub trait Extractor {
+    /// Extract diagnostics from the output, given as lines without ANSI codes
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic>;
+}
+
+/// Build a regex from a pattern known to be valid
+fn regex(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("invalid built-in pattern")
+}
+
+/// One diagnostic per matching line, described by named groups
+///
+/// Recognized groups: `message` (required), `file`, `line`, `column` and
+/// `severity`.
+pub struct LineExtractor {
+    tool: String,
+    pattern: Regex,
+    severity: Severity,
+}
+
+impl LineExtractor {
+    /// Create an extractor reporting lines that match `pattern`
+    pub fn new(tool: &str, pattern: Regex, severity: Severity) -> Self {
+        Self {
+            tool: tool.to_string(),
+            pattern,
+            severity,
+        }
+    }
+
+    /// Build an extractor from a `[[summary.patterns]]` entry
+    fn from_settings(settings: &PatternSettings) -> Result<Self> {
+        let pattern = Regex::new(&settings.regex).map_err(|e| {
+            TellmeError::InvalidConfig(format!(
+                "Invalid summary pattern '{}': {}",
+                settings.name, e
+            ))
+        })?;
+        if !pattern.capture_names().any(|name| name == Some("message")) {
+            return Err(TellmeError::InvalidConfig(format!(
+                "Invalid summary pattern '{}': missing a (?P<message>...) group",
+                settings.name
+            )));
+        }
+        Ok(Self::new(&settings.name, pattern, settings.severity))
+    }
+
+    /// gcc and clang: `file:line:col: error: message`
+    fn gcc() -> Self {
+        Self::new(
+            "gcc",
+            regex(
+                r"^(?P<file>[^\s:][^:]*):(?P<line>\d+):(?P<column>\d+): (?P<severity>(?:fatal )?error|warning): (?P<message>.+)$",
+            ),
+            Severity::Error,
+        )
+    }
+
+    /// pytest short summary: `FAILED tests/test_x.py::test_y - AssertionError`
+    fn pytest() -> Self {
+        Self::new(
+            "pytest",
+            regex(r"^(?:FAILED|ERROR) (?P<message>(?P<file>[^\s:]+\.py)(?:::\S+)?(?: - .*)?)$"),
+            Severity::Error,
+        )
+    }
+}
+
+impl Extractor for LineExtractor {
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic> {
+        lines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let caps = self.pattern.captures(line)?;
+                let severity = caps
+                    .name("severity")
+                    .map_or(self.severity, |m| Severity::parse(m.as_str()));
+                let message = caps.name("message").map_or(*line, |m| m.as_str());
+                Some(
+                    Diagnostic::new(&self.tool, severity, message, index + 1)
+                        .locate(&caps)
+                        .with_text(&[line]),
+                )
+            })
+            .collect()
+    }
+}
+
+/// rustc and cargo: `error[E0308]: message` blocks with a `-->` location
+pub struct RustExtractor {
+    header: Regex,
+    location: Regex,
+    noise: Regex,
+}
+
+impl Default for RustExtractor {
+    fn default() -> Self {
+        Self {
+            header: regex(r"^(?P<severity>error|warning)(?:\[E\d+\])?: (?P<message>.+)$"),
+            location: regex(r"^\s*--> (?P<file>[^:]+):(?P<line>\d+):(?P<column>\d+)"),
+            noise: regex(
+                r"^(?:aborting due to|could not compile|build failed|`[^`]+` \(.+\) generated \d+ warnings?|\d+ warnings? emitted)",
+            ),
+        }
+    }
+}
+
+impl Extractor for RustExtractor {
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for (start, line) in lines.iter().enumerate() {
+            let Some(caps) = self.header.captures(line) else {
+                continue;
+            };
+            if self.noise.is_match(&caps["message"]) {
+                continue;
+            }
+
+            // The block runs until a blank line or the next diagnostic
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| l.trim().is_empty() || self.header.is_match(l))
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            let block = &lines[start..end];
+
+            let mut diagnostic =
+                Diagnostic::new("rustc", Severity::parse(&caps["severity"]), line, start + 1);
+            if let Some(location) = block.iter().find_map(|l| self.location.captures(l)) {
+                diagnostic = diagnostic.locate(&location);
+            }
+            diagnostics.push(diagnostic.with_text(block));
+        }
+
+        diagnostics
+    }
+}
+
+/// Python: `Traceback (most recent call last):` up to the exception line
+pub struct PythonExtractor {
+    frame: Regex,
+}
+
+impl Default for PythonExtractor {
+    fn default() -> Self {
+        Self {
+            frame: regex(r#"^\s*File "(?P<file>[^"]+)", line (?P<line>\d+)"#),
+        }
+    }
+}
+
+impl Extractor for PythonExtractor {
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for (start, line) in lines.iter().enumerate() {
+            if line.trim_end() != "Traceback (most recent call last):" {
+                continue;
+            }
+
+            // Frames are indented; the first unindented line is the exception
+            let Some(offset) = lines[start + 1..]
+                .iter()
+                .position(|l| !l.starts_with([' ', '\t']))
+            else {
+                continue;
+            };
+            let end = start + 1 + offset;
+            let block = &lines[start..=end];
+
+            let mut diagnostic = Diagnostic::new("python", Severity::Error, lines[end], start + 1);
+            // The innermost frame is where the exception was raised
+            if let Some(frame) = block.iter().rev().find_map(|l| self.frame.captures(l)) {
+                diagnostic = diagnostic.locate(&frame);
+            }
+            diagnostics.push(diagnostic.with_text(block));
+        }
+
+        diagnostics
+    }
+}
+
+/// Go: `--- FAIL: TestName` blocks and `file.go:line:col: message` build errors
+pub struct GoExtractor {
+    failure: Regex,
+    location: Regex,
+    build_error: Regex,
+}
+
+impl Default for GoExtractor {
+    fn default() -> Self {
+        Self {
+            failure: regex(r"^\s*--- FAIL: (?P<test>\S+)"),
+            location: regex(r"^\s+(?P<file>[\w./-]+\.go):(?P<line>\d+): (?P<message>.+)$"),
+            build_error: regex(
+                r"^(?P<file>[^\s:]+\.go):(?P<line>\d+):(?P<column>\d+): (?P<message>.+)$",
+            ),
+        }
+    }
+}
+
+impl Extractor for GoExtractor {
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for (start, line) in lines.iter().enumerate() {
+            if let Some(caps) = self.build_error.captures(line) {
+                diagnostics.push(
+                    Diagnostic::new("go", Severity::Error, &caps["message"], start + 1)
+                        .locate(&caps)
+                        .with_text(&[line]),
+                );
+                continue;
+            }
+
+            let Some(caps) = self.failure.captures(line) else {
+                continue;
+            };
+            let indent = line.len() - line.trim_start().len();
+
+            // The test's log lines are indented deeper than its FAIL line
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| l.trim().is_empty() || l.len() - l.trim_start().len() <= indent)
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            let block = &lines[start..end];
+
+            let location = block[1..].iter().find_map(|l| self.location.captures(l));
+            let message = match &location {
+                Some(location) => format!("{} failed: {}", &caps["test"], &location["message"]),
+                None => format!("{} failed", &caps["test"]),
+            };
+            let mut diagnostic = Diagnostic::new("go", Severity::Error, &message, start + 1);
+            if let Some(location) = &location {
+                diagnostic = diagnostic.locate(location);
+            }
+            diagnostics.push(diagnostic.with_text(block));
+        }
+
+        diagnostics
+    }
+}
+
+/// npm: consecutive `npm ERR!` (or `npm error`) lines
+pub struct NpmExtractor {
+    line: Regex,
+}
+
+impl Default for NpmExtractor {
+    fn default() -> Self {
+        Self {
+            line: regex(r"^npm (?:ERR!|error)(?: (?P<message>.*))?$"),
+        }
+    }
+}
+
+impl Extractor for NpmExtractor {
+    fn extract(&self, lines: &[&str]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut start = 0;
+
+        while start < lines.len() {
+            if !self.line.is_match(lines[start]) {
+                start += 1;
+                continue;
+            }
+
+            let end = lines[start..]
+                .iter()
+                .position(|l| !self.line.is_match(l))
+                .map_or(lines.len(), |offset| start + offset);
+            let block = &lines[start..end];
+
+            let message = block
+                .iter()
+                .filter_map(|l| self.line.captures(l)?.name("message"))
+                .map(|m| m.as_str())
+                .find(|m| !m.trim().is_empty())
+                .unwrap_or("npm error");
+            diagnostics
+                .push(Diagnostic::new("npm", Severity::Error, message, start + 1).with_text(block));
+            start = end;
+        }
+
+        diagnostics
+    }
+}
+
+/// Runs a set of extractors over a command's output
+pub struct Summarizer {
+    extractors: Vec<Box<dyn Extractor>>,
+}
+
+impl Default for Summarizer {
+    /// A summarizer with the built-in extractors
+    fn default() -> Self {
+        Self {
+            extractors: vec![
+                Box::new(RustExtractor::default()),
+                Box::new(LineExtractor::gcc()),
+                Box::new(PythonExtractor::default()),
+                Box::new(LineExtractor::pytest()),
+                Box::new(GoExtractor::default()),
+                Box::new(NpmExtractor::default()),
+            ],
+        }
+    }
+}
+
+impl Summarizer {
+    /// The built-in extractors plus the patterns configured in settings
+    pub fn from_settings(settings: &SummarySettings) -> Result<Self> {
+        let mut summarizer = Self::default();
+        for pattern in &settings.patterns {
+            summarizer.register(Box::new(LineExtractor::from_settings(pattern)?));
+        }
+        Ok(summarizer)
+    }
+
+    /// Add an extractor
+    pub fn register(&mut self, extractor: Box<dyn Extractor>) {
+        self.extractors.push(extractor);
+    }
+
+    /// Extract the diagnostics of every extractor, in output order
+    pub fn summarize(&self, output: &str) -> Vec<Diagnostic> {
+        let lines: Vec<&str> = output.lines().collect();
+        let mut diagnostics: Vec<Diagnostic> = self
+            .extractors
+            .iter()
+            .flat_map(|extractor| extractor.extract(&lines))
+            .collect();
+        diagnostics.sort_by_key(|d| d.output_line);
+        diagnostics
+    }
+}
+
+/// Number of errors and warnings in a summary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    /// Diagnostics with [`Severity::Error`]
+    pub errors: usize,
+
+    /// Diagnostics with [`Severity::Warning`]
+    pub warnings: usize,
+}
+
+/// Count the errors and warnings among diagnostics
+pub fn count(diagnostics: &[Diagnostic]) -> Counts {
+    let warnings = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .count();
+    Counts {
+        errors: diagnostics.len() - warnings,
+        warnings,
+    }
+}
+
+/// Describe diagnostic counts (e.g. `2 errors, 1 warning`)
+pub fn describe(diagnostics: &[Diagnostic]) -> String {
+    if diagnostics.is_empty() {
+        return "no errors or warnings found".to_string();
+    }
+
+    let plural = |count: usize, word: &str| {
+        format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+    };
+    let Counts { errors, warnings } = count(diagnostics);
+
+    match (errors, warnings) {
+        (_, 0) => plural(errors, "error"),
+        (0, _) => plural(warnings, "warning"),
+        _ => format!(
+            "{}, {}",
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(extractor: impl Extractor, output: &str) -> Vec<Diagnostic> {
+        let lines: Vec<&str> = output.lines().collect();
+        extractor.extract(&lines)
+    }
+
+    #[test]
+    fn test_rust() {
+        let output = "   Compiling demo v0.1.0
+error[E0308]: mismatched types
+ --> src/main.rs:4:18
+  |
+4 |     let x: i32 = \"a\";
+  |                  ^^^ expected `i32`, found `&str`
+
+warning: unused variable: `y`
+ --> src/lib.rs:2:9
+
+error: could not compile `demo` (bin \"demo\") due to 1 previous error";
+
+        let diagnostics = extract(RustExtractor::default(), output);
+        assert_eq!(diagnostics.len(), 2);
+
+        let error = &diagnostics[0];
+        assert_eq!(error.message, "error[E0308]: mismatched types");
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(error.file.as_deref(), Some("src/main.rs"));
+        assert_eq!((error.line, error.column), (Some(4), Some(18)));
+        assert_eq!(error.output_line, 2);
+        assert_eq!(error.text.lines().count(), 5);
+
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].file.as_deref(), Some("src/lib.rs"));
+    }
+
+    #[test]
+    fn test_gcc() {
+        let output = "main.c: In function 'main':
+main.c:3:5: error: 'x' undeclared (first use in this function)
+lib.c:10:1: warning: control reaches end of non-void function";
+
+        let diagnostics = extract(LineExtractor::gcc(), output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("main.c"));
+        assert_eq!(diagnostics[0].line, Some(3));
+        assert_eq!(
+            diagnostics[0].message,
+            "'x' undeclared (first use in this function)"
+        );
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_python() {
+        let output = "starting
+Traceback (most recent call last):
+  File \"app.py\", line 10, in <module>
+    main()
+  File \"lib/util.py\", line 3, in main
+    raise ValueError(\"bad input\")
+ValueError: bad input
+done";
+
+        let diagnostics = extract(PythonExtractor::default(), output);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "ValueError: bad input");
+        assert_eq!(diagnostics[0].file.as_deref(), Some("lib/util.py"));
+        assert_eq!(diagnostics[0].line, Some(3));
+        assert_eq!(diagnostics[0].text.lines().count(), 6);
+    }
+
+    #[test]
+    fn test_pytest() {
+        let output = "===== short test summary info =====
+FAILED tests/test_api.py::test_login - AssertionError: 401 != 200
+ERROR tests/test_db.py
+FAILED to connect";
+
+        let diagnostics = extract(LineExtractor::pytest(), output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "tests/test_api.py::test_login - AssertionError: 401 != 200"
+        );
+        assert_eq!(diagnostics[0].file.as_deref(), Some("tests/test_api.py"));
+        assert_eq!(diagnostics[1].file.as_deref(), Some("tests/test_db.py"));
+    }
+
+    #[test]
+    fn test_go() {
+        let output = "=== RUN   TestAdd
+--- FAIL: TestAdd (0.00s)
+    math_test.go:12: expected 4, got 5
+FAIL
+./main.go:7:2: undefined: foo";
+
+        let diagnostics = extract(GoExtractor::default(), output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "TestAdd failed: expected 4, got 5");
+        assert_eq!(diagnostics[0].file.as_deref(), Some("math_test.go"));
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(diagnostics[1].message, "undefined: foo");
+        assert_eq!(diagnostics[1].column, Some(2));
+    }
+
+    #[test]
+    fn test_npm() {
+        let output = "> build
+npm ERR! code ELIFECYCLE
+npm ERR! errno 1
+npm ERR!
+other
+npm error Missing script: \"start\"";
+
+        let diagnostics = extract(NpmExtractor::default(), output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "code ELIFECYCLE");
+        assert_eq!(diagnostics[0].text.lines().count(), 3);
+        assert_eq!(diagnostics[1].message, "Missing script: \"start\"");
+    }
+
+    #[test]
+    fn test_summarizer_with_custom_pattern() {
+        let settings = SummarySettings {
+            patterns: vec![PatternSettings {
+                name: "lint".to_string(),
+                regex: r"^LINT (?P<file>\S+): (?P<message>.+)$".to_string(),
+                severity: Severity::Warning,
+            }],
+        };
+        let summarizer = Summarizer::from_settings(&settings).unwrap();
+
+        let output = "LINT a.sh: quote this\nmain.c:1:1: error: boom";
+        let diagnostics = summarizer.summarize(output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].tool, "lint");
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].tool, "gcc");
+        assert_eq!(describe(&diagnostics), "1 error, 1 warning");
+        assert_eq!(
+            count(&diagnostics),
+            Counts {
+                errors: 1,
+                warnings: 1
+            }
+        );
+
+        let invalid = SummarySettings {
+            patterns: vec![PatternSettings {
+                name: "bad".to_string(),
+                regex: "^no message group$".to_string(),
+                severity: Severity::Error,
+            }],
+        };
+        assert!(Summarizer::from_settings(&invalid).is_err());
+    }
+}
@@ -1,24 +1,33 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
 use std::path::PathBuf;
+use std::thread::JoinHandle;
 use tempfile::TempDir;
 
 struct TestEnv {
     config_dir: TempDir,
     temp_dir: TempDir,
+    data_dir: TempDir,
     pid: String,
+    session: String,
 }
 
 impl TestEnv {
     fn new() -> Self {
         let config_dir = tempfile::tempdir().expect("failed to create config dir");
         let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let data_dir = tempfile::tempdir().expect("failed to create data dir");
         let pid = std::process::id().to_string();
+        let session = format!("session-{}", pid);
         Self {
             config_dir,
             temp_dir,
+            data_dir,
             pid,
+            session,
         }
     }
 
@@ -26,9 +35,57 @@ impl TestEnv {
         let mut cmd = Command::new(env!("CARGO_BIN_EXE_tellme"));
         cmd.env("TELLME_CONFIG_DIR", self.config_dir.path())
             .env("TELLME_TEMP_DIR", self.temp_dir.path())
-            .env("TELLME_SHELL_PID", &self.pid);
+            .env("TELLME_DATA_DIR", self.data_dir.path())
+            .env("TELLME_SHELL_PID", &self.pid)
+            .env("TELLME_SESSION", &self.session);
         cmd
     }
+
+    /// Replace config.toml
+    fn write_config(&self, toml: impl AsRef<str>) {
+        fs::write(self.config_dir.path().join("config.toml"), toml.as_ref())
+            .expect("failed to write config");
+    }
+
+    /// Simulate the shell hooks capturing a command, returning its output file
+    ///
+    /// Also runs the step that keeps the capture, which the shell leaves to
+    /// the background, so its result can be checked right away.
+    fn capture(&self, command: &str, output: &str, exit_code: i32) -> PathBuf {
+        self.capture_with_env(command, output, exit_code, &[])
+    }
+
+    /// Like [`TestEnv::capture`], with extra environment variables for the hooks
+    fn capture_with_env(
+        &self,
+        command: &str,
+        output: &str,
+        exit_code: i32,
+        envs: &[(&str, &str)],
+    ) -> PathBuf {
+        let hook = |args: &[&str]| {
+            self.cmd()
+                .envs(envs.iter().copied())
+                .args(args)
+                .assert()
+                .success()
+        };
+        let assert = hook(&["internal", "--prepare", command]);
+        let log_path = String::from_utf8_lossy(&assert.get_output().stdout)
+            .trim()
+            .to_string();
+        let log_path = PathBuf::from(log_path);
+        fs::write(&log_path, output).expect("failed to write log");
+
+        hook(&[
+            "internal",
+            "--finish",
+            "--exit-code",
+            &exit_code.to_string(),
+        ]);
+        hook(&["internal", "--store"]);
+        log_path
+    }
 }
 
 #[test]
@@ -65,6 +122,208 @@ fn test_status_workflow() {
         .stdout(predicate::str::contains("DISABLED"));
 }
 
+#[test]
+fn test_json_output() {
+    let env = TestEnv::new();
+    let json = |args: &[&str]| -> serde_json::Value {
+        let assert = env.cmd().args(args).arg("--json").assert().success();
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json")
+    };
+
+    assert_eq!(json(&["status"])["recording"], false);
+    let status = json(&["on"]);
+    assert_eq!(status["recording"], true);
+    assert_eq!(status["session"], env.session.as_str());
+
+    let skip = json(&["config", "--add", "secret_cmd"]);
+    assert_eq!(skip["changed"], true);
+    assert!(
+        skip["skip_commands"]
+            .as_array()
+            .unwrap()
+            .contains(&"secret_cmd".into())
+    );
+    assert_eq!(json(&["config", "--add", "secret_cmd"])["changed"], false);
+
+    env.capture("make", "main.c:3:5: error: 'x' undeclared\n", 2);
+    let path = env.temp_dir.path().join("out.log");
+    let saved = json(&["--summary", "-o", path.to_str().unwrap()]);
+    assert_eq!(saved["command"], "make");
+    assert_eq!(saved["path"], path.to_str().unwrap());
+    assert_eq!(saved["format"], "text");
+    assert_eq!(saved["output_bytes"], 34);
+    assert_eq!(saved["saved_bytes"], fs::metadata(&path).unwrap().len());
+    assert_eq!(saved["summary"]["errors"], 1);
+
+    // Interactive commands refuse --json instead of ignoring it
+    for command in ["browse", "follow"] {
+        env.cmd()
+            .args([command, "--json"])
+            .assert()
+            .failure()
+            .code(2)
+            .stderr(predicate::str::contains(format!(
+                "--json is not supported by 'tellme {}'",
+                command
+            )));
+    }
+}
+
+#[test]
+fn test_json_output_of_captures() {
+    let env = TestEnv::new();
+    let json = |args: &[&str]| -> serde_json::Value {
+        let assert = env.cmd().args(args).arg("--json").assert().success();
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json")
+    };
+    env.cmd().arg("on").assert().success();
+    env.capture("printf 'ok\\nfixed\\n'", "ok\nerror: boom\n", 1);
+
+    let shown = json(&["show"]);
+    assert_eq!(shown["command"], "printf 'ok\\nfixed\\n'");
+    assert_eq!(shown["output"], "ok\nerror: boom\n");
+
+    let matches = json(&["grep", "boom", "-C", "1"]);
+    assert_eq!(matches[0]["capture"]["exit_code"], 1);
+    assert_eq!(
+        matches[0]["groups"][0],
+        serde_json::json!([
+            { "number": 1, "text": "ok", "is_match": false },
+            { "number": 2, "text": "error: boom", "is_match": true },
+        ])
+    );
+    assert_eq!(json(&["grep", "nothing"]), serde_json::json!([]));
+
+    // The output is reported instead of streamed
+    let rerun = json(&["rerun"]);
+    assert_eq!(rerun["previous"]["id"], shown["id"]);
+    assert_eq!(rerun["capture"]["exit_code"], 0);
+    assert_eq!(rerun["output"], "ok\nfixed\n");
+    assert_eq!(
+        rerun["diff"],
+        serde_json::json!({ "added": 1, "removed": 1 })
+    );
+
+    let diff = json(&["diff"]);
+    assert_eq!(diff["old"]["id"], shown["id"]);
+    assert_eq!(diff["new"]["id"], rerun["capture"]["id"]);
+    assert_eq!(diff["summary"]["added"], 1);
+    let lines = diff["lines"].as_array().unwrap();
+    assert!(lines.contains(&serde_json::json!({ "kind": "delete", "text": "-error: boom" })));
+    assert!(lines.contains(&serde_json::json!({ "kind": "insert", "text": "+fixed" })));
+
+    // A shell that exited without cleaning up
+    let mut child = std::process::Command::new("true")
+        .spawn()
+        .expect("failed to spawn");
+    let dead_pid = child.id();
+    child.wait().expect("failed to wait");
+    env.cmd()
+        .env("TELLME_SHELL_PID", dead_pid.to_string())
+        .env("TELLME_SESSION", "dead-session")
+        .args(["internal", "--prepare", "make"])
+        .assert()
+        .success();
+
+    let swept = json(&["gc"]);
+    assert_eq!(swept.as_array().unwrap().len(), 1);
+    assert_eq!(swept[0]["session"], "dead-session");
+    assert_eq!(swept[0]["pid"], dead_pid);
+    assert_eq!(swept[0]["alive"], false);
+    assert_eq!(json(&["gc"]), serde_json::json!([]));
+}
+
+#[test]
+fn test_no_color_when_piped() {
+    let env = TestEnv::new();
+
+    env.cmd()
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[").not());
+
+    env.cmd()
+        .arg("status")
+        .env("CLICOLOR_FORCE", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b["));
+}
+
+#[test]
+fn test_exit_codes() {
+    let env = TestEnv::new();
+
+    // Nothing was captured while recording is off
+    env.cmd()
+        .arg("-o")
+        .arg(env.temp_dir.path().join("out.log"))
+        .assert()
+        .failure()
+        .code(4)
+        .stderr(predicate::str::contains("Recording is disabled"));
+
+    env.cmd().arg("on").assert().success();
+    env.cmd()
+        .arg("-o")
+        .arg(env.temp_dir.path().join("out.log"))
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No previous command record found"));
+
+    env.cmd()
+        .args(["show", "--no-pager", "ffffffff"])
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No capture with id 'ffffffff'"));
+
+    env.cmd().args(["grep", "("]).assert().failure().code(2);
+
+    env.cmd()
+        .arg("status")
+        .env("TELLME_SHELL_PID", "not-a-pid")
+        .assert()
+        .failure()
+        .code(6)
+        .stderr(predicate::str::contains("Invalid TELLME_SHELL_PID value"));
+}
+
+#[test]
+fn test_invalid_config() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.write_config("[capture\n");
+
+    // The shell hooks keep capturing with the default settings
+    env.cmd()
+        .args(["internal", "--should-prepare", "make"])
+        .assert()
+        .success()
+        .stdout("true\n")
+        .stderr(predicate::str::contains("Invalid config file"))
+        .stderr(predicate::str::contains("using the default settings"));
+    for args in [
+        &["internal", "--prepare", "make"][..],
+        &["internal", "--finish", "--exit-code", "0"],
+        &["internal", "--store"],
+    ] {
+        env.cmd().args(args).assert().success().stderr("");
+    }
+
+    // Commands run by the user report the problem
+    for command in ["status", "list", "show"] {
+        env.cmd()
+            .arg(command)
+            .assert()
+            .failure()
+            .code(6)
+            .stderr(predicate::str::contains("Invalid config file"));
+    }
+}
+
 #[test]
 fn test_capture_workflow() {
     let env = TestEnv::new();
@@ -74,7 +333,7 @@ fn test_capture_workflow() {
 
     // 1. Check should-prepare
     env.cmd()
-        .args(&["internal", "--should-prepare", "echo hello"])
+        .args(["internal", "--should-prepare", "echo hello"])
         .assert()
         .success()
         .stdout(predicate::str::contains("true"));
@@ -83,7 +342,7 @@ fn test_capture_workflow() {
     // This should output the path to the log file
     let assert = env
         .cmd()
-        .args(&["internal", "--prepare", "echo hello"])
+        .args(["internal", "--prepare", "echo hello"])
         .assert()
         .success();
 
@@ -99,7 +358,24 @@ fn test_capture_workflow() {
     fs::write(&log_path, "hello world output\x1b[31m colored\x1b[0m")
         .expect("failed to write to log");
 
-    // 3. Capture command (tellme without args)
+    // The output cannot be saved until the command has finished
+    env.cmd()
+        .arg("-o")
+        .arg(env.temp_dir.path().join("early.log"))
+        .assert()
+        .failure()
+        .code(5)
+        .stderr(predicate::str::contains(
+            "The last command 'echo hello' has not finished yet",
+        ));
+
+    // 3. Finish
+    env.cmd()
+        .args(["internal", "--finish", "--exit-code", "0"])
+        .assert()
+        .success();
+
+    // 4. Capture command (tellme without args)
     // We'll output to a specific file to verify content
     let result_file = env.temp_dir.path().join("result.log");
 
@@ -125,19 +401,8 @@ fn test_capture_raw() {
     let env = TestEnv::new();
     env.cmd().arg("on").assert().success();
 
-    // Prepare
-    let assert = env
-        .cmd()
-        .args(&["internal", "--prepare", "echo color"])
-        .assert()
-        .success();
-    let log_path_str = String::from_utf8_lossy(&assert.get_output().stdout)
-        .trim()
-        .to_string();
-    let log_path = PathBuf::from(log_path_str);
-
     // Simulate shell writing ANSI
-    fs::write(&log_path, "\x1b[31mRED\x1b[0m").expect("failed to write log");
+    env.capture("echo color", "\x1b[31mRED\x1b[0m", 0);
 
     // Capture with --raw
     let result_file = env.temp_dir.path().join("raw.log");
@@ -159,28 +424,1067 @@ fn test_skip_commands() {
 
     // "tellme" is in default skip list
     env.cmd()
-        .args(&["internal", "--should-prepare", "tellme status"])
+        .args(["internal", "--should-prepare", "tellme status"])
         .assert()
         .success()
         .stdout(predicate::str::contains("false"));
 
     // Add "secret_cmd" to skip list
     env.cmd()
-        .args(&["config", "--add", "secret_cmd"])
+        .args(["config", "--add", "secret_cmd"])
         .assert()
         .success();
 
     // Verify it is skipped
     env.cmd()
-        .args(&["internal", "--should-prepare", "secret_cmd"])
+        .args(["internal", "--should-prepare", "secret_cmd"])
         .assert()
         .success()
         .stdout(predicate::str::contains("false"));
 
     // Verify normal command is still ok
     env.cmd()
-        .args(&["internal", "--should-prepare", "ls"])
+        .args(["internal", "--should-prepare", "ls"])
         .assert()
         .success()
         .stdout(predicate::str::contains("true"));
 }
+
+#[test]
+fn test_clipboard_backends() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.capture("make", "built", 0);
+
+    let file = env.temp_dir.path().join("clipboard.txt");
+    env.write_config(format!(
+        "[clipboard]\nbackend = \"file\"\nfile = \"{}\"\n",
+        file.display()
+    ));
+    env.cmd()
+        .arg("-c")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Output copied to {}",
+            file.display()
+        )));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "built");
+
+    // A missing clipboard tool is an error, not a panic
+    env.write_config("[clipboard]\nbackend = \"xclip\"\n");
+    env.cmd()
+        .arg("-c")
+        .env("PATH", "")
+        .assert()
+        .failure()
+        .code(8)
+        .stderr(predicate::str::contains("cannot run xclip"));
+}
+
+#[test]
+fn test_select_lines() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    let output: Vec<String> = (1..=200).map(|i| format!("line {}", i)).collect();
+    env.capture(
+        "make",
+        &format!("{}\nmain.c:3:5: error: boom\n", output.join("\n")),
+        2,
+    );
+
+    let saved = env.temp_dir.path().join("saved.log");
+    let save = |args: &[&str]| {
+        env.cmd()
+            .arg("-o")
+            .arg(&saved)
+            .args(args)
+            .assert()
+            .success();
+        fs::read_to_string(&saved).unwrap()
+    };
+    assert!(save(&["--tail", "2"]).ends_with("\n\nline 200\nmain.c:3:5: error: boom\n"));
+    assert!(save(&["--head", "1"]).ends_with("\n\nline 1\n"));
+    assert!(save(&["--lines", "10:11"]).ends_with("\n\nline 10\nline 11\n"));
+    assert!(save(&["--grep", "line 5\\d", "-C", "0"]).contains("\nline 50\n"));
+    assert!(save(&["--grep", "^line 1$", "-C", "1"]).ends_with("\n\nline 1\nline 2\n"));
+    let summary = save(&["--summary-only"]);
+    assert!(summary.contains("Summary: 1 error"));
+    assert!(!summary.contains("line 1"));
+
+    // Selectors exclude each other
+    env.cmd()
+        .args(["--head", "1", "--tail", "1"])
+        .assert()
+        .failure()
+        .code(2);
+    env.cmd()
+        .args(["-o", "x.log", "--grep", "nothing matches"])
+        .assert()
+        .failure()
+        .code(3);
+
+    // Large clipboard payloads are cut down to the tail when configured
+    let clipboard = env.temp_dir.path().join("clipboard.txt");
+    env.write_config(format!(
+        "[clipboard]\nbackend = \"file\"\nfile = \"{}\"\nmax_size_kb = 1\nauto_tail = 3\n",
+        clipboard.display()
+    ));
+    env.cmd()
+        .arg("-c")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("copying only the last 3 lines"));
+    assert_eq!(
+        fs::read_to_string(&clipboard).unwrap(),
+        "line 199\nline 200\nmain.c:3:5: error: boom"
+    );
+
+    // An explicit selection is kept as it is
+    env.cmd()
+        .args(["-c", "--lines", "1:2"])
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+    assert_eq!(fs::read_to_string(&clipboard).unwrap(), "line 1\nline 2");
+}
+
+#[test]
+fn test_capture_compressed() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.write_config("[capture]\ncompression = \"zstd\"\n");
+
+    let log_path = env.capture("echo zipped", "zipped output", 0);
+
+    // The capture is stored compressed...
+    let stored = fs::read(&log_path).expect("failed to read log");
+    assert!(!stored.starts_with(b"zipped"));
+
+    // ...and saved as gzip because of the .gz extension
+    let result_file = env.temp_dir.path().join("result.log.gz");
+    env.cmd().arg("-o").arg(&result_file).assert().success();
+
+    let saved = fs::read(&result_file).expect("failed to read result");
+    assert!(saved.starts_with(&[0x1f, 0x8b]));
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(saved.as_slice()),
+        &mut content,
+    )
+    .expect("failed to decompress result");
+    assert!(content.contains("Command:\necho zipped"));
+    assert!(content.contains("zipped output"));
+}
+
+#[test]
+fn test_finish_archives_in_background() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.write_config("[archive]\nenabled = true\n");
+
+    let assert = env
+        .cmd()
+        .args(["internal", "--prepare", "make"])
+        .assert()
+        .success();
+    let log_path = String::from_utf8_lossy(&assert.get_output().stdout)
+        .trim()
+        .to_string();
+    fs::write(log_path, "built").expect("failed to write log");
+    env.cmd()
+        .args(["internal", "--finish", "--exit-code", "0"])
+        .assert()
+        .success();
+
+    // The prompt does not wait for the archive, which a detached tellme writes
+    let index = env.data_dir.path().join("archive/index.json");
+    for _ in 0..200 {
+        if index.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(25));
+    }
+    let entries: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&index).expect("failed to read index"))
+            .expect("invalid index");
+    assert_eq!(entries[0]["command"], "make");
+}
+
+#[test]
+fn test_archive_survives_cleanup() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.write_config("[archive]\nenabled = true\n");
+
+    env.capture("./deploy.sh", "deploy failed", 1);
+    env.cmd().args(["internal", "--cleanup"]).assert().success();
+
+    // The shell's temp files are gone, but the archive keeps the capture
+    let index = fs::read_to_string(env.data_dir.path().join("archive/index.json"))
+        .expect("failed to read index");
+    let entries: serde_json::Value = serde_json::from_str(&index).expect("invalid index");
+    assert_eq!(entries[0]["command"], "./deploy.sh");
+    assert_eq!(entries[0]["exit_code"], 1);
+    let id = entries[0]["id"].as_str().unwrap();
+
+    let result_file = env.temp_dir.path().join("deploy.log");
+    env.cmd()
+        .args(["--id", id, "-o"])
+        .arg(&result_file)
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(&result_file).expect("failed to read result");
+    assert!(content.contains("Command:\n./deploy.sh"));
+    assert!(content.contains("deploy failed"));
+}
+
+#[test]
+fn test_list_captures() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.cmd()
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No captures found."));
+
+    env.capture("cargo build", "error[E0308]: mismatched types", 101);
+    env.capture("ls -la", "Cargo.toml", 0);
+
+    env.cmd()
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cargo build"))
+        .stdout(predicate::str::contains("ls -la"));
+
+    let assert = env
+        .cmd()
+        .args(["list", "--failed", "--since", "1h", "--json"])
+        .assert()
+        .success();
+    let records: serde_json::Value =
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json");
+    let records = records.as_array().expect("expected an array");
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["command"], "cargo build");
+    assert_eq!(records[0]["exit_code"], 101);
+
+    env.cmd()
+        .args(["list", "--grep", "ls", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ls -la"))
+        .stdout(predicate::str::contains("cargo build").not());
+}
+
+#[test]
+fn test_show_capture() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.capture("make", "\x1b[31mbuild failed\x1b[0m", 2);
+    env.capture("ls", "Makefile", 0);
+
+    // Defaults to the last command; stdout is not a TTY, so no pager is used
+    env.cmd()
+        .arg("show")
+        .env("PAGER", "false")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Command:\nls"))
+        .stdout(predicate::str::contains("Makefile"));
+
+    // An older capture, by id
+    let assert = env
+        .cmd()
+        .args(["list", "--failed", "--json"])
+        .assert()
+        .success();
+    let records: serde_json::Value =
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json");
+    let id = records[0]["id"].as_str().unwrap().to_string();
+
+    env.cmd()
+        .args(["show", &id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Command:\nmake"))
+        .stdout(predicate::str::contains("build failed"))
+        .stdout(predicate::str::contains("\x1b[31m").not());
+
+    env.cmd()
+        .args(["show", "--raw", &id])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[31mbuild failed"));
+
+    // tellme never captures itself, even with an empty skip list
+    env.cmd().args(["config", "--clear"]).assert().success();
+    env.cmd()
+        .args(["internal", "--should-prepare", "tellme show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("false"));
+}
+
+#[test]
+fn test_grep_captures() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.capture(
+        "curl localhost:8080",
+        "Trying...\nConnection refused\nbye",
+        7,
+    );
+    env.capture("curl localhost:9090", "Trying...\nconnection refused", 0);
+    env.capture("ls", "Cargo.toml", 0);
+
+    env.cmd()
+        .args(["grep", "-i", "connection refused"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("curl localhost:8080"))
+        .stdout(predicate::str::contains("curl localhost:9090"))
+        .stdout(predicate::str::contains("Trying..."))
+        .stdout(predicate::str::contains("2 matching lines in 2 captures"));
+
+    env.cmd()
+        .args(["grep", "--failed-only", "-C", "0", "refused"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("curl localhost:8080"))
+        .stdout(predicate::str::contains("curl localhost:9090").not())
+        .stdout(predicate::str::contains("Trying...").not());
+
+    env.cmd()
+        .args(["grep", "-F", "Cargo.tom."])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No matches."));
+}
+
+#[test]
+fn test_rerun_capture() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.capture("echo fixed", "broken", 1);
+
+    env.cmd()
+        .arg("rerun")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("$ echo fixed"))
+        .stdout(predicate::str::contains("Exit code: 1 → 0"))
+        .stdout(predicate::str::contains("+1 lines added, -1 removed"));
+
+    // The new run is captured and becomes the last command
+    env.cmd()
+        .args(["show", "--no-pager"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("fixed"));
+
+    env.cmd()
+        .arg("rerun")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit code: 0 (unchanged)"))
+        .stdout(predicate::str::contains("Output unchanged"));
+
+    let assert = env.cmd().args(["list", "--json"]).assert().success();
+    let records: serde_json::Value =
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json");
+    assert_eq!(records.as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_rerun_from_another_shell() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.capture("echo fixed", "broken", 1);
+
+    // Another terminal reruns this shell's last command
+    let other_pid = std::os::unix::process::parent_id().to_string();
+    env.cmd()
+        .env("TELLME_SESSION", "other-terminal")
+        .env("TELLME_SHELL_PID", &other_pid)
+        .args(["rerun", "--shell", &env.session])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit code: 1 → 0"));
+
+    // The new run is recorded in the terminal that ran it
+    env.cmd()
+        .env("TELLME_SESSION", "other-terminal")
+        .env("TELLME_SHELL_PID", &other_pid)
+        .args(["show", "--no-pager"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("fixed"));
+    env.cmd()
+        .args(["show", "--no-pager"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("broken"));
+}
+
+#[test]
+fn test_diff_captures() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.cmd()
+        .arg("diff")
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains(
+            "No previous command record found.",
+        ));
+
+    env.capture(
+        "cargo test",
+        "test a ... ok\ntest b ... FAILED\nin 1.20s",
+        101,
+    );
+    env.capture("ls", "Cargo.toml", 0);
+    env.capture("cargo test", "test a ... ok\ntest b ... ok\nin 0.98s", 0);
+
+    env.cmd()
+        .arg("diff")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-test b ... FAILED"))
+        .stdout(predicate::str::contains("+test b ... ok"))
+        .stdout(predicate::str::contains("+in 0.98s"));
+
+    env.cmd()
+        .args(["diff", "--normalize"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+test b ... ok"))
+        .stdout(predicate::str::contains(" in <duration>"))
+        .stdout(predicate::str::contains("+in").not());
+
+    env.cmd()
+        .args(["diff", "--side-by-side"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("|"));
+
+    let assert = env.cmd().args(["list", "--json"]).assert().success();
+    let records: serde_json::Value =
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json");
+    let ls = records[1]["id"].as_str().unwrap();
+
+    env.cmd()
+        .args(["diff", ls])
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No earlier run of 'ls'"));
+
+    env.cmd()
+        .args(["diff", ls, ls])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("identical"));
+}
+
+#[test]
+fn test_last_failed() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.cmd()
+        .arg("--last-failed")
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No failed command found"));
+
+    env.capture("cargo build", "error[E0308]: mismatched types", 101);
+    env.capture("ls", "Cargo.toml", 0);
+    env.capture("cat Cargo.toml", "[package]", 0);
+
+    let result_file = env.temp_dir.path().join("failed.log");
+    env.cmd()
+        .arg("--last-failed")
+        .arg("-o")
+        .arg(&result_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Last failed: cargo build (exit 101",
+        ));
+
+    let content = fs::read_to_string(&result_file).expect("failed to read result");
+    assert!(content.contains("Command:\ncargo build"));
+    assert!(content.contains("mismatched types"));
+
+    env.cmd()
+        .args(["--last-failed", "--id", "abc"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_summary() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.capture(
+        "cargo build",
+        "   Compiling demo v0.1.0\nerror[E0308]: mismatched types\n --> src/main.rs:4:18\n\nerror: could not compile `demo`",
+        101,
+    );
+
+    let result_file = env.temp_dir.path().join("build.log");
+    env.cmd()
+        .arg("--summary")
+        .arg("-o")
+        .arg(&result_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(1 error)"));
+
+    let content = fs::read_to_string(&result_file).expect("failed to read result");
+    let summary = content.find("Summary: 1 error").expect("missing summary");
+    let output = content.find("   Compiling demo").expect("missing output");
+    assert!(summary < output);
+
+    // The name alone does not change the format
+    let json_file = env.temp_dir.path().join("build.json");
+    env.cmd()
+        .args(["--summary", "-o"])
+        .arg(&json_file)
+        .assert()
+        .success();
+    assert!(
+        fs::read_to_string(&json_file)
+            .expect("failed to read log")
+            .contains("Summary: 1 error")
+    );
+
+    env.cmd()
+        .args(["--summary", "--format", "auto", "-o"])
+        .arg(&json_file)
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&json_file).expect("failed to read json"))
+            .expect("invalid json");
+    assert_eq!(json["command"], "cargo build");
+    assert_eq!(
+        json["summary"][0]["message"],
+        "error[E0308]: mismatched types"
+    );
+    assert_eq!(json["summary"][0]["file"], "src/main.rs");
+    assert!(json["output"].as_str().unwrap().contains("Compiling demo"));
+}
+
+#[test]
+fn test_quickfix_format() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.capture(
+        "make",
+        "cc -c main.c\nmain.c:3:5: error: 'x' undeclared\nmake: *** [main.o] Error 1",
+        2,
+    );
+
+    let errors_file = env.temp_dir.path().join("errors.err");
+    env.cmd()
+        .args(["--format", "auto", "-o"])
+        .arg(&errors_file)
+        .assert()
+        .success();
+    // Paths are resolved against the directory the command ran in
+    let main_c = std::env::current_dir().unwrap().join("main.c");
+    assert_eq!(
+        fs::read_to_string(&errors_file).expect("failed to read errors"),
+        format!("{}:3:5: error: 'x' undeclared\n", main_c.display())
+    );
+
+    let problems_file = env.temp_dir.path().join("problems.json");
+    env.cmd()
+        .args(["--format", "vscode", "-o"])
+        .arg(&problems_file)
+        .assert()
+        .success();
+    let problems: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&problems_file).expect("failed to read json"))
+            .expect("invalid json");
+    assert_eq!(problems[0]["resource"], main_c.to_str().unwrap());
+    assert_eq!(problems[0]["startLineNumber"], 3);
+}
+
+#[test]
+fn test_follow_running_command() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.cmd()
+        .arg("follow")
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No running command found."));
+
+    let assert = env
+        .cmd()
+        .args(["internal", "--prepare", "./long-build.sh"])
+        .assert()
+        .success();
+    let log_path = PathBuf::from(
+        String::from_utf8_lossy(&assert.get_output().stdout)
+            .trim()
+            .to_string(),
+    );
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            fs::write(&log_path, "\x1b[1mstep 1\x1b[0m\nstep 2\n").expect("failed to write log");
+            env.cmd()
+                .args(["internal", "--finish", "--exit-code", "3"])
+                .assert()
+                .success();
+        });
+
+        env.cmd()
+            .arg("follow")
+            .timeout(std::time::Duration::from_secs(10))
+            .assert()
+            .success()
+            .stdout("step 1\nstep 2\n")
+            .stderr(predicate::str::contains("Following './long-build.sh'"))
+            .stderr(predicate::str::contains("Finished with exit 3"));
+    });
+}
+
+#[test]
+fn test_other_shells() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    env.cmd()
+        .arg("shells")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No shells with captures found."));
+
+    env.capture("make deploy", "deployed to staging", 0);
+
+    // A fresh terminal, without the shell hook, reads another shell's capture
+    let result_file = env.temp_dir.path().join("other.log");
+    env.cmd()
+        .env_remove("TELLME_SHELL_PID")
+        .env_remove("TELLME_SESSION")
+        .args(["--shell", &env.pid, "-o"])
+        .arg(&result_file)
+        .assert()
+        .success();
+    let content = fs::read_to_string(&result_file).expect("failed to read result");
+    assert!(content.contains("Command:\nmake deploy"));
+    assert!(content.contains("deployed to staging"));
+
+    env.cmd()
+        .env_remove("TELLME_SHELL_PID")
+        .env_remove("TELLME_SESSION")
+        .args(["show", "--no-pager", "--shell", "session-"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deployed to staging"));
+
+    env.cmd()
+        .args(["show", "--no-pager", "--shell", "no-such-shell"])
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("No shell 'no-such-shell' found."));
+
+    env.cmd()
+        .arg("shells")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&env.pid))
+        .stdout(predicate::str::contains("yes"))
+        .stdout(predicate::str::contains("make deploy"));
+
+    let assert = env.cmd().args(["shells", "--json"]).assert().success();
+    let shells: serde_json::Value =
+        serde_json::from_slice(&assert.get_output().stdout).expect("invalid json");
+    assert_eq!(shells[0]["session"], env.session);
+    assert_eq!(shells[0]["pid"].to_string(), env.pid);
+    assert_eq!(shells[0]["alive"], true);
+    assert_eq!(shells[0]["last"]["command"], "make deploy");
+}
+
+#[test]
+fn test_gc_dead_shells() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.capture("ls", "Cargo.toml", 0);
+
+    // Leave files behind for a shell that has exited without cleaning up
+    let mut child = std::process::Command::new("true")
+        .spawn()
+        .expect("failed to spawn");
+    let dead_pid = child.id().to_string();
+    let dead_session = format!("session-{}", dead_pid);
+    child.wait().expect("failed to wait");
+    env.cmd()
+        .env("TELLME_SHELL_PID", &dead_pid)
+        .env("TELLME_SESSION", &dead_session)
+        .args(["internal", "--prepare", "make"])
+        .assert()
+        .success();
+    assert!(
+        env.temp_dir
+            .path()
+            .join(format!(".tellme_output_{}", dead_session))
+            .exists()
+    );
+
+    env.cmd()
+        .arg("gc")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Removed the files of 1 dead shells ({})",
+            dead_pid
+        )));
+    assert!(
+        !env.temp_dir
+            .path()
+            .join(format!(".tellme_output_{}", dead_session))
+            .exists()
+    );
+
+    // The live shell keeps its capture
+    env.cmd()
+        .args(["show", "--no-pager"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Cargo.toml"));
+
+    env.cmd()
+        .arg("gc")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to clean up."));
+}
+
+#[test]
+fn test_new_session() {
+    let env = TestEnv::new();
+
+    let first = env
+        .cmd()
+        .args(["internal", "--new-session"])
+        .assert()
+        .success();
+    let first = String::from_utf8_lossy(&first.get_output().stdout)
+        .trim()
+        .to_string();
+    assert_eq!(first.len(), 36);
+
+    let second = env
+        .cmd()
+        .args(["internal", "--new-session"])
+        .assert()
+        .success();
+    assert_ne!(
+        String::from_utf8_lossy(&second.get_output().stdout).trim(),
+        first
+    );
+
+    env.cmd()
+        .env("TELLME_SESSION", "../escape")
+        .arg("status")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid TELLME_SESSION value"));
+}
+
+#[test]
+fn test_private_temp_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::new();
+    let mode = |path: PathBuf| fs::metadata(path).expect("missing").permissions().mode() & 0o777;
+    fs::set_permissions(env.temp_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    env.cmd().arg("on").assert().success();
+    env.capture("env", "API_TOKEN=hunter2", 0);
+
+    // A directory chosen with TELLME_TEMP_DIR is restricted to the user
+    assert_eq!(mode(env.temp_dir.path().to_path_buf()), 0o700);
+    assert_eq!(
+        mode(
+            env.temp_dir
+                .path()
+                .join(format!(".tellme_output_{}", env.session))
+        ),
+        0o600
+    );
+
+    // Without TELLME_TEMP_DIR the per-user runtime dir is used
+    let runtime = tempfile::tempdir().expect("failed to create runtime dir");
+    env.cmd()
+        .env_remove("TELLME_TEMP_DIR")
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .args(["internal", "--prepare", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            runtime.path().join("tellme").to_string_lossy().to_string(),
+        ));
+    assert_eq!(mode(runtime.path().join("tellme")), 0o700);
+
+    let link = runtime.path().join("link");
+    std::os::unix::fs::symlink(env.temp_dir.path(), &link).expect("failed to link");
+    env.cmd()
+        .env("TELLME_TEMP_DIR", &link)
+        .arg("status")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("it is a symlink"));
+
+    // A directory other users could write to is restricted too
+    let shared = runtime.path().join("shared");
+    fs::create_dir(&shared).expect("failed to create dir");
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+    env.cmd()
+        .env("TELLME_TEMP_DIR", &shared)
+        .arg("status")
+        .assert()
+        .success();
+    assert_eq!(mode(shared), 0o700);
+}
+
+/// Answer one HTTP request, returning the URL to send it to and the raw request
+fn paste_server(status: &'static str, body: &'static str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+    let url = format!("http://{}/paste", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("no request");
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        let (head_len, content_length) = loop {
+            let n = stream.read(&mut buf).expect("failed to read request");
+            assert!(n > 0, "connection closed early");
+            request.extend_from_slice(&buf[..n]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map_or(0, |value| value.trim().parse().unwrap());
+                break (end + 4, length);
+            }
+        };
+        while request.len() < head_len + content_length {
+            let n = stream.read(&mut buf).expect("failed to read body");
+            assert!(n > 0, "connection closed early");
+            request.extend_from_slice(&buf[..n]);
+        }
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .expect("failed to respond");
+        String::from_utf8_lossy(&request).into_owned()
+    });
+    (url, handle)
+}
+
+#[test]
+fn test_share_capture() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+    env.capture(
+        "deploy --token=abc123",
+        "Authorization: Bearer s3cr3t\nfailed",
+        1,
+    );
+
+    let share = |url: &str| {
+        env.write_config(format!(
+            "[share]\nurl = \"{}\"\nresponse = \"/url\"\n\
+                 headers = {{ Authorization = \"Bearer ${{PASTE_TOKEN}}\" }}\n",
+            url
+        ));
+        let mut cmd = env.cmd();
+        cmd.arg("share")
+            .env("PASTE_TOKEN", "paste-token")
+            .env_remove("http_proxy")
+            .env_remove("HTTP_PROXY")
+            .env_remove("all_proxy")
+            .env_remove("ALL_PROXY");
+        cmd
+    };
+
+    let (url, server) = paste_server("200 OK", r#"{"url": "https://paste.example.com/x1"}"#);
+    share(&url)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("https://paste.example.com/x1"))
+        .stdout(predicate::str::contains("2 secrets redacted"));
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /paste "), "{}", request);
+    assert!(request.contains("Authorization: Bearer paste-token\r\n"));
+    assert!(request.contains("deploy --token=[REDACTED]"), "{}", request);
+    assert!(request.contains("Authorization: Bearer [REDACTED]\nfailed"));
+    assert!(!request.contains("s3cr3t"));
+    // The curl config with the token is gone
+    let share_files = || {
+        fs::read_dir(env.temp_dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(".tellme_share_")
+            })
+            .count()
+    };
+    assert_eq!(share_files(), 0);
+
+    let (url, server) = paste_server("503 Service Unavailable", "try later");
+    share(&url)
+        .assert()
+        .failure()
+        .code(9)
+        .stderr(predicate::str::contains("503: try later"));
+    server.join().unwrap();
+    assert_eq!(share_files(), 0);
+
+    // A token missing from the environment, and no service at all
+    share("http://127.0.0.1:1/")
+        .env_remove("PASTE_TOKEN")
+        .assert()
+        .failure()
+        .code(6)
+        .stderr(predicate::str::contains("PASTE_TOKEN"));
+    fs::remove_file(env.config_dir.path().join("config.toml")).unwrap();
+    env.cmd()
+        .arg("share")
+        .assert()
+        .failure()
+        .code(6)
+        .stderr(predicate::str::contains("No paste service configured"));
+}
+
+/// Wait for a file written by a background hook
+fn wait_for_file(path: &std::path::Path) -> String {
+    for _ in 0..200 {
+        match fs::read_to_string(path) {
+            Ok(contents) if contents.ends_with('\n') => return contents,
+            _ => std::thread::sleep(std::time::Duration::from_millis(25)),
+        }
+    }
+    panic!("{} was never written", path.display());
+}
+
+#[test]
+fn test_hooks() {
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    let dir = env.temp_dir.path();
+    let failure = dir.join("failure.log");
+    let saved = dir.join("saved.log");
+    env.write_config(format!(
+        "[hooks]\n\
+             on_capture_complete = \"sleep 5\"\n\
+             on_failure = \"echo \\\"$TELLME_COMMAND $TELLME_EXIT_CODE\\\" > '{}'\"\n\
+             on_save = \"{{ echo $TELLME_SAVED_PATH; cat; echo; }} > '{}'\"\n",
+        failure.display(),
+        saved.display()
+    ));
+
+    // A slow hook does not hold up the shell
+    let start = std::time::Instant::now();
+    env.capture("make test", "1 failed", 2);
+    assert!(start.elapsed() < std::time::Duration::from_secs(4));
+    assert_eq!(wait_for_file(&failure), "make test 2\n");
+
+    let target = dir.join("out.log");
+    env.cmd()
+        .args(["-o", target.to_str().unwrap()])
+        .assert()
+        .success();
+    let contents = wait_for_file(&saved);
+    let (path, json) = contents.split_once('\n').unwrap();
+    assert_eq!(path, target.to_str().unwrap());
+    let event: serde_json::Value = serde_json::from_str(json).expect("invalid json");
+    assert_eq!(event["hook"], "on_save");
+    assert_eq!(event["capture"]["command"], "make test");
+    assert_eq!(event["capture"]["exit_code"], 2);
+
+    // Successful commands only run on_capture_complete
+    fs::remove_file(&failure).unwrap();
+    env.capture("ls", "", 0);
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(!failure.exists());
+}
+
+#[test]
+fn test_notify_long_commands() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::new();
+    env.cmd().arg("on").assert().success();
+
+    // A stub notify-send recording its arguments
+    let bin = env.temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let shown = env.temp_dir.path().join("shown.txt");
+    let stub = bin.join("notify-send");
+    fs::write(
+        &stub,
+        format!(
+            "#!/bin/sh\nprintf '%s|' \"$@\" > '{0}.tmp'\necho >> '{0}.tmp'\nmv '{0}.tmp' '{0}'\n",
+            shown.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let notify_after = |secs: u64| {
+        env.write_config(format!(
+            "[notify]\nenabled = true\nmin_duration_secs = {}\nbackend = \"notify-send\"\n",
+            secs
+        ));
+    };
+    notify_after(0);
+    env.capture_with_env("cargo build --release", "output", 101, &[("PATH", &path)]);
+    let args = wait_for_file(&shown);
+    assert!(
+        args.starts_with("--app-name=tellme|--icon|dialog-error|--|Failed with exit 101 after "),
+        "{}",
+        args
+    );
+    assert!(args.ends_with("|cargo build --release|\n"), "{}", args);
+
+    // Short commands are not worth a notification
+    fs::remove_file(&shown).unwrap();
+    notify_after(3600);
+    env.capture_with_env("ls", "output", 0, &[("PATH", &path)]);
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(!shown.exists());
+}
@@ -0,0 +1,62 @@
+use std::fs;
+
+use tellme::{CaptureQuery, CaptureSession, CaptureSource, CaptureStore, Config, TellmeError};
+
+#[test]
+fn test_read_captures_through_library() {
+    let config_dir = tempfile::tempdir().expect("failed to create config dir");
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let data_dir = tempfile::tempdir().expect("failed to create data dir");
+    let config = Config::from_dirs(
+        config_dir.path(),
+        temp_dir.path(),
+        data_dir.path(),
+        "library-test",
+        std::process::id(),
+    )
+    .expect("failed to load config");
+    let session = CaptureSession::new(&config);
+    for (command, output, exit_code) in [("make", "built", 0), ("cargo test", "1 failed", 101)] {
+        let path = session
+            .prepare_new_command(command)
+            .expect("failed to prepare");
+        fs::write(path, output).expect("failed to write output");
+        session
+            .finish_command(Some(exit_code))
+            .expect("failed to finish");
+    }
+
+    let store = CaptureStore::new(&config);
+    let records = store.list().expect("failed to list");
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].meta.command, "cargo test");
+    assert_eq!(records[0].meta.session, "library-test");
+    assert_eq!(records[1].source, CaptureSource::History);
+
+    let failed = store
+        .query(&CaptureQuery {
+            failed: true,
+            ..CaptureQuery::default()
+        })
+        .expect("failed to query");
+    assert_eq!(failed.len(), 1);
+    assert_eq!(
+        failed[0].read_output().expect("failed to read"),
+        b"1 failed"
+    );
+
+    // A finished capture streams its whole output at once
+    let mut streamed = Vec::new();
+    let finished = tellme::follow::follow(
+        &store,
+        &records[0],
+        &mut streamed,
+        tellme::follow::POLL_INTERVAL,
+    )
+    .expect("failed to follow");
+    assert_eq!(streamed, b"1 failed");
+    assert_eq!(finished.and_then(|meta| meta.exit_code), Some(101));
+
+    // An empty prefix matches every capture
+    assert!(matches!(store.find(""), Err(TellmeError::Ambiguous { .. })));
+}